@@ -0,0 +1,87 @@
+//! Synthetic bloaty CSVs and Cargo.lock shared by the benchmarks
+
+use std::fmt::Write;
+
+/// Number of crates in the synthetic dependency graph
+const CRATES: usize = 200;
+
+const SECTIONS: &[&str] = &[
+    ".text",
+    ".text",
+    ".text",
+    ".rodata",
+    ".data.rel.ro",
+    ".eh_frame",
+];
+const MODULES: &[&str] = &[
+    "de", "ser", "io", "sync", "fmt", "util", "raw", "parse", "iter",
+];
+const TYPES: &[&str] = &["Reader", "Writer", "Builder", "State", "Error", "Config"];
+
+/// A small deterministic pseudo random generator, so every run sees the same input
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> usize {
+        // xorshift64
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 as usize
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.next() % items.len()]
+    }
+}
+
+fn crate_name(i: usize) -> String {
+    format!("crate_{i}")
+}
+
+/// A Cargo.lock where `crate_0` is the root and every crate depends on a few later ones
+pub fn generate_lockfile() -> String {
+    let mut rng = Rng(0x5eed);
+    let mut lock = String::from("version = 3\n");
+    for i in 0..CRATES {
+        let deps: Vec<String> = (0..3)
+            .map(|_| i + 1 + rng.next() % 20)
+            .filter(|&dep| dep < CRATES)
+            .map(|dep| format!("\"{}\"", crate_name(dep)))
+            .collect();
+        let _ = write!(
+            lock,
+            "\n[[package]]\nname = \"{}\"\nversion = \"1.0.0\"\ndependencies = [{}]\n",
+            crate_name(i),
+            deps.join(", ")
+        );
+    }
+    lock
+}
+
+/// A bloaty CSV with `rows` records mixing plain paths, trait impls, closures, generics,
+/// runtime symbols and unattributed C symbols
+pub fn generate_csv(rows: usize) -> String {
+    let mut rng = Rng(0xb10a7);
+    let mut csv = String::with_capacity(rows * 64);
+    csv.push_str("sections,symbols,vmsize,filesize\n");
+    for i in 0..rows {
+        let krate = crate_name(rng.next() % CRATES);
+        let other = crate_name(rng.next() % CRATES);
+        let module = rng.pick(MODULES);
+        let ty = rng.pick(TYPES);
+        let symbol = match rng.next() % 8 {
+            0 => format!("<{krate}::{module}::{ty} as core::fmt::Debug>::fmt"),
+            1 => format!("<{krate}::{module}::{ty}>::new"),
+            2 => format!("{krate}::{module}::run::{{closure#{}}}", i % 3),
+            3 => format!("core::ptr::drop_in_place::<{other}::{module}::{ty}>"),
+            4 => format!("<alloc::vec::Vec<{krate}::{ty}> as core::clone::Clone>::clone"),
+            5 if i % 5 == 0 => "memcpy".to_string(),
+            6 if i % 7 == 0 => format!("c_helper_{i}"),
+            _ => format!("{krate}::{module}::{ty}::f{}", i % 100),
+        };
+        let size = 16 + rng.next() % 4096;
+        let _ = writeln!(csv, "{},\"{symbol}\",{size},{size}", rng.pick(SECTIONS));
+    }
+    csv
+}
@@ -0,0 +1,44 @@
+//! Benchmark `from_csv_with_options` on synthetic bloaty CSVs with a synthetic Cargo.lock
+//!
+//! Run with `cargo bench`, `BLOATY_BENCH_ROWS=50000` adds a case with that many rows
+
+mod common;
+
+use bloaty_metafile::{ConvertOptions, from_csv_with_options};
+use common::{generate_csv, generate_lockfile};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use std::{hint::black_box, path::PathBuf};
+
+/// Row counts benchmarked by default
+const ROWS: &[usize] = &[10_000, 100_000, 1_000_000];
+
+fn bench_from_csv(c: &mut Criterion) {
+    let lock_path: PathBuf = std::env::temp_dir().join("bloaty-metafile-bench.lock");
+    std::fs::write(&lock_path, generate_lockfile()).expect("Failed to write lockfile");
+    let options = ConvertOptions {
+        lock: Some(lock_path.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+
+    let mut rows = ROWS.to_vec();
+    if let Some(n) = std::env::var("BLOATY_BENCH_ROWS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+    {
+        rows.push(n);
+    }
+
+    let mut group = c.benchmark_group("from_csv");
+    group.sample_size(10);
+    for rows in rows {
+        let csv = generate_csv(rows);
+        group.throughput(Throughput::Elements(rows as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(rows), &csv, |b, csv| {
+            b.iter(|| from_csv_with_options(black_box(csv), "BINARY", &options))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_from_csv);
+criterion_main!(benches);
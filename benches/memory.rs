@@ -0,0 +1,179 @@
+//! Measure the heap used by `Tree::new` on a large synthetic CSV with a counting allocator,
+//! and what interning node names saves against every node owning its name
+//!
+//! Run with `cargo bench --bench memory`, `BLOATY_BENCH_ROWS=50000` sets the number of rows
+
+mod common;
+
+use bloaty_metafile::{ConvertOptions, Tree};
+use common::{generate_csv, generate_lockfile};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::{HashMap, HashSet},
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Rows of the CSV unless `BLOATY_BENCH_ROWS` is set
+const ROWS: usize = 1_000_000;
+
+/// The system allocator, counting allocations and live bytes
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+fn grow(size: usize) {
+    let current = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(current, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { System.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+            grow(new_size);
+        }
+        new_ptr
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Heap use while running a closure
+struct Usage {
+    allocations: usize,
+    /// Highest number of live bytes above the start
+    peak: usize,
+    /// Bytes still live at the end, held by the returned value
+    retained: usize,
+}
+
+/// Run `f` and measure its heap use, the returned value is kept alive
+fn measure<T>(f: impl FnOnce() -> T) -> (T, Usage) {
+    let start = CURRENT.load(Ordering::Relaxed);
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    PEAK.store(start, Ordering::Relaxed);
+    let value = f();
+    let usage = Usage {
+        allocations: ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        peak: PEAK.load(Ordering::Relaxed).saturating_sub(start),
+        retained: CURRENT.load(Ordering::Relaxed).saturating_sub(start),
+    };
+    (value, usage)
+}
+
+/// The node names of the tree before interning: the parent's key and the node's name are
+/// separate copies, and equal names in different places are not shared
+struct OwnedNode {
+    _name: Box<str>,
+    _nodes: HashMap<Box<str>, OwnedNode>,
+}
+
+/// The node names of the tree as interned now, one shared copy per distinct name
+struct SharedNode {
+    _name: Rc<str>,
+    _nodes: HashMap<Rc<str>, SharedNode>,
+}
+
+fn children(json: &serde_json::Value) -> impl Iterator<Item = (&str, &serde_json::Value)> {
+    json["children"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|child| (child["name"].as_str().unwrap_or_default(), child))
+}
+
+fn owned(name: &str, json: &serde_json::Value) -> OwnedNode {
+    OwnedNode {
+        _name: name.into(),
+        _nodes: children(json)
+            .map(|(name, child)| (name.into(), owned(name, child)))
+            .collect(),
+    }
+}
+
+/// The shared copy of `name`, like the tree's interner
+fn intern(names: &mut HashSet<Rc<str>>, name: &str) -> Rc<str> {
+    if let Some(name) = names.get(name) {
+        return name.clone();
+    }
+    let name: Rc<str> = name.into();
+    names.insert(name.clone());
+    name
+}
+
+fn shared(names: &mut HashSet<Rc<str>>, name: Rc<str>, json: &serde_json::Value) -> SharedNode {
+    SharedNode {
+        _nodes: children(json)
+            .map(|(name, child)| {
+                let name = intern(names, name);
+                (name.clone(), shared(names, name, child))
+            })
+            .collect(),
+        _name: name,
+    }
+}
+
+fn print_usage(label: &str, usage: &Usage) {
+    println!(
+        "{label:<16} {:>12} {:>14} {:>14}",
+        usage.allocations, usage.peak, usage.retained
+    );
+}
+
+fn main() {
+    let lock_path = std::env::temp_dir().join("bloaty-metafile-bench.lock");
+    std::fs::write(&lock_path, generate_lockfile()).expect("Failed to write lockfile");
+    let options = ConvertOptions {
+        lock: Some(lock_path.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    let rows = std::env::var("BLOATY_BENCH_ROWS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(ROWS);
+    let csv = generate_csv(rows);
+
+    let (tree, tree_usage) = measure(|| Tree::new(&csv, &options).expect("Failed to build tree"));
+    let json = tree.to_nested_json();
+    drop(tree);
+
+    // The same tree structure with both ways of storing names, only the names differ
+    let (shared, shared_usage) = measure(|| {
+        let mut names = HashSet::new();
+        let root = intern(&mut names, "");
+        let root = shared(&mut names, root, &json);
+        (names, root)
+    });
+    drop(shared);
+    let (owned, owned_usage) = measure(|| owned("", &json));
+    drop(owned);
+
+    println!("{rows} rows");
+    println!(
+        "{:<16} {:>12} {:>14} {:>14}",
+        "", "allocations", "peak bytes", "retained bytes"
+    );
+    print_usage("Tree::new", &tree_usage);
+    print_usage("interned names", &shared_usage);
+    print_usage("owned names", &owned_usage);
+}
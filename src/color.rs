@@ -0,0 +1,101 @@
+use std::{ffi::OsStr, fmt, io::IsTerminal};
+
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// When to use colors in the stderr reports
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    /// Color if stderr is a terminal and `NO_COLOR` is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// ANSI colors of warnings, errors and report headers on stderr, plain text if disabled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    pub enabled: bool,
+}
+
+impl Style {
+    /// The style for stderr, detecting the terminal and `NO_COLOR` with [`ColorChoice::Auto`]
+    pub fn stderr(choice: ColorChoice) -> Self {
+        Self::new(
+            choice,
+            std::env::var_os("NO_COLOR").as_deref(),
+            std::io::stderr().is_terminal(),
+        )
+    }
+
+    /// `no_color` is the value of `NO_COLOR`, which only disables colors if it is not empty
+    /// (<https://no-color.org>). An explicit `Always` or `Never` wins over both
+    pub fn new(choice: ColorChoice, no_color: Option<&OsStr>, is_terminal: bool) -> Self {
+        let enabled = match choice {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => is_terminal && no_color.is_none_or(OsStr::is_empty),
+        };
+        Self { enabled }
+    }
+
+    fn paint(&self, color: &str, s: impl fmt::Display) -> String {
+        if self.enabled {
+            format!("{color}{s}{RESET}")
+        } else {
+            s.to_string()
+        }
+    }
+
+    /// `Warning: <message>` in yellow
+    pub fn warning(&self, message: impl fmt::Display) -> String {
+        self.paint(YELLOW, format_args!("Warning: {message}"))
+    }
+
+    /// `Error: <message>` in red
+    pub fn error(&self, message: impl fmt::Display) -> String {
+        self.paint(RED, format_args!("Error: {message}"))
+    }
+
+    /// A table with its first line (the header) in bold
+    pub fn table(&self, table: &str) -> String {
+        match table.split_once('\n') {
+            Some((header, rows)) => format!("{}\n{rows}", self.paint(BOLD, header)),
+            None => self.paint(BOLD, table),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ColorChoice, Style};
+    use std::ffi::OsStr;
+
+    #[test]
+    fn test_no_color() {
+        let no_color = Some(OsStr::new("1"));
+        let style = Style::new(ColorChoice::Auto, no_color, true);
+        assert!(!style.enabled);
+        assert_eq!(style.warning("skipped 2 rows"), "Warning: skipped 2 rows");
+        assert_eq!(style.error("failed"), "Error: failed");
+        assert_eq!(
+            style.table("crate  bytes\nfoo       10\n"),
+            "crate  bytes\nfoo       10\n"
+        );
+        assert!(!style.warning("x").contains('\x1b'));
+
+        // An empty NO_COLOR is ignored, an explicit choice wins
+        assert!(Style::new(ColorChoice::Auto, Some(OsStr::new("")), true).enabled);
+        assert!(Style::new(ColorChoice::Always, no_color, false).enabled);
+        assert!(!Style::new(ColorChoice::Never, None, true).enabled);
+        assert!(!Style::new(ColorChoice::Auto, None, false).enabled);
+
+        let style = Style::new(ColorChoice::Auto, None, true);
+        assert_eq!(style.warning("x"), "\x1b[33mWarning: x\x1b[0m");
+        assert_eq!(style.error("x"), "\x1b[31mError: x\x1b[0m");
+        assert_eq!(style.table("crate\nfoo\n"), "\x1b[1mcrate\x1b[0m\nfoo\n");
+    }
+}
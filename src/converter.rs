@@ -0,0 +1,114 @@
+use crate::{BloatyError, ConvertOptions, Result, Tree};
+use cargo_lock::{Lockfile, dependency::Tree as DependencyTree};
+use serde_metafile::Metafile;
+
+/// Converts several CSVs against the same Cargo.lock, which is loaded and resolved only once
+///
+/// # Example
+///
+/// ```no_run
+/// use bloaty_metafile::Converter;
+///
+/// let converter = Converter::with_lock("Cargo.lock")?;
+/// for bin in ["server", "client"] {
+///     let csv = std::fs::read_to_string(format!("{bin}.csv")).unwrap();
+///     let metafile = converter.convert(&csv, bin, 0, false)?;
+/// }
+/// # Ok::<(), bloaty_metafile::BloatyError>(())
+/// ```
+pub struct Converter {
+    dep_tree: DependencyTree,
+}
+
+impl Converter {
+    /// Load and resolve the lockfile at `path`
+    /// Unlike [`Tree::new`], a missing or invalid lockfile is an error
+    pub fn with_lock(path: &str) -> Result<Converter> {
+        let error = |source| BloatyError::LockfileLoad {
+            path: path.to_string(),
+            source,
+        };
+        let dep_tree = Lockfile::load(path)
+            .map_err(error)?
+            .dependency_tree()
+            .map_err(error)?;
+        Ok(Converter::from_tree(dep_tree))
+    }
+
+    /// Use an already resolved dependency tree
+    pub fn from_tree(dep_tree: DependencyTree) -> Converter {
+        Converter { dep_tree }
+    }
+
+    /// Convert bloaty CSV output to esbuild metafile format, like [`crate::from_csv`]
+    pub fn convert(
+        &self,
+        csv: &str,
+        name: &str,
+        deep: usize,
+        no_sections: bool,
+    ) -> Result<Metafile> {
+        let options = ConvertOptions {
+            deep,
+            no_sections,
+            ..Default::default()
+        };
+        self.convert_with_options(csv, name, &options)
+    }
+
+    /// Like [`Converter::convert`] using [`ConvertOptions`], `lock` and `no_lock` are ignored
+    pub fn convert_with_options(
+        &self,
+        csv: &str,
+        name: &str,
+        options: &ConvertOptions,
+    ) -> Result<Metafile> {
+        let tree = Tree::new_with_tree(csv, &self.dep_tree, options)?;
+        Ok(tree.to_metafile(name, options))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Converter;
+    use crate::{BloatyError, packages::test::dependency_tree};
+
+    #[test]
+    fn test_converter() {
+        let converter = Converter::from_tree(dependency_tree(&[
+            ("app", "0.1.0", &["foo", "bar"]),
+            ("foo", "1.0.0", &["bar"]),
+            ("bar", "1.0.0", &[]),
+        ]));
+
+        let server = r#"
+sections,symbols,vmsize,filesize
+.text,app::main,1,1
+.text,foo::a,2,2
+"#;
+        let client = r#"
+sections,symbols,vmsize,filesize
+.text,app::main,1,1
+.text,bar::b,3,3
+"#;
+        let meta = converter
+            .convert(server, "server", 0, false)
+            .expect("Failed to convert");
+        assert_eq!(meta.inputs["app/foo/.text/a"].bytes, 2);
+        assert!(meta.outputs.contains_key("server"));
+
+        let meta = converter
+            .convert(client, "client", 0, false)
+            .expect("Failed to convert");
+        assert_eq!(meta.inputs["app/bar/.text/b"].bytes, 3);
+        assert!(!meta.inputs.contains_key("app/foo/.text/a"));
+    }
+
+    #[test]
+    fn test_converter_missing_lock() {
+        assert!(matches!(
+            Converter::with_lock("missing.lock"),
+            Err(BloatyError::LockfileLoad { .. })
+        ));
+    }
+}
@@ -0,0 +1,500 @@
+use crate::{
+    error::{BloatyError, Result},
+    report::{format_size, size_cell, write_table},
+};
+use serde::Serialize;
+use serde_metafile::Metafile;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+};
+
+/// Size change of a single input between two metafiles
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InputDelta {
+    pub path: String,
+    pub base_bytes: u64,
+    pub current_bytes: u64,
+    pub delta: i64,
+}
+
+impl InputDelta {
+    fn new(path: &str, base_bytes: u64, current_bytes: u64) -> Self {
+        Self {
+            path: path.to_string(),
+            base_bytes,
+            current_bytes,
+            delta: current_bytes as i64 - base_bytes as i64,
+        }
+    }
+}
+
+/// Differences between a baseline metafile and the current one
+/// Each list is sorted by absolute delta (largest first), then by path
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MetafileDiffReport {
+    pub added: Vec<InputDelta>,
+    pub removed: Vec<InputDelta>,
+    pub changed: Vec<InputDelta>,
+    pub total_delta: i64,
+}
+
+impl MetafileDiffReport {
+    /// Returns true if no input was added, removed or changed
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compare the inputs of two metafiles by path
+///
+/// Inputs only present in `current` are reported as added, inputs only present
+/// in `base` as removed, and inputs present in both with different sizes as changed.
+pub fn diff_metafiles(base: &Metafile, current: &Metafile) -> MetafileDiffReport {
+    let mut report = MetafileDiffReport::default();
+
+    for (path, input) in &current.inputs {
+        match base.inputs.get(path) {
+            None => report.added.push(InputDelta::new(path, 0, input.bytes)),
+            Some(old) if old.bytes != input.bytes => {
+                report
+                    .changed
+                    .push(InputDelta::new(path, old.bytes, input.bytes))
+            }
+            Some(_) => {}
+        }
+    }
+
+    for (path, input) in &base.inputs {
+        if !current.inputs.contains_key(path) {
+            report.removed.push(InputDelta::new(path, input.bytes, 0));
+        }
+    }
+
+    for list in [&mut report.added, &mut report.removed, &mut report.changed] {
+        list.sort_by(|a, b| {
+            b.delta
+                .unsigned_abs()
+                .cmp(&a.delta.unsigned_abs())
+                .then_with(|| a.path.cmp(&b.path))
+        });
+    }
+
+    report.total_delta = report
+        .added
+        .iter()
+        .chain(&report.removed)
+        .chain(&report.changed)
+        .map(|d| d.delta)
+        .sum();
+
+    report
+}
+
+/// Size changes of crates between two trees, see [`diff_crates`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CrateDiffReport {
+    /// Crates whose size changed, `path` is the crate name
+    pub crates: Vec<InputDelta>,
+    pub total_delta: i64,
+}
+
+/// Compare per-crate totals (see `Tree::crate_totals`) of a baseline and the current build
+///
+/// Only crates whose size changed are reported (including added and removed crates),
+/// sorted by absolute delta (largest first), then by name.
+pub fn diff_crates(base: &[(String, u64)], current: &[(String, u64)]) -> CrateDiffReport {
+    let base: HashMap<&str, u64> = base.iter().map(|(name, b)| (name.as_str(), *b)).collect();
+    let current: HashMap<&str, u64> = current
+        .iter()
+        .map(|(name, b)| (name.as_str(), *b))
+        .collect();
+
+    let names: BTreeSet<&str> = base.keys().chain(current.keys()).copied().collect();
+    let mut crates: Vec<InputDelta> = names
+        .into_iter()
+        .map(|name| {
+            let old = base.get(name).copied().unwrap_or(0);
+            let new = current.get(name).copied().unwrap_or(0);
+            InputDelta::new(name, old, new)
+        })
+        .filter(|d| d.delta != 0)
+        .collect();
+    crates.sort_by(|a, b| {
+        b.delta
+            .unsigned_abs()
+            .cmp(&a.delta.unsigned_abs())
+            .then_with(|| a.path.cmp(&b.path))
+    });
+
+    let total_delta = crates.iter().map(|d| d.delta).sum();
+    CrateDiffReport {
+        crates,
+        total_delta,
+    }
+}
+
+/// Size of a crate compared to its budget, see [`check_budget`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BudgetEntry {
+    pub name: String,
+    pub bytes: u64,
+    pub budget: u64,
+    /// Bytes over (positive) or under (negative) the budget
+    pub delta: i64,
+}
+
+impl BudgetEntry {
+    pub fn is_over(&self) -> bool {
+        self.delta > 0
+    }
+}
+
+/// Per-crate sizes compared to a budget, see [`check_budget`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct BudgetReport {
+    /// Crates with a budget, sorted by delta (most over budget first), then by name
+    pub crates: Vec<BudgetEntry>,
+}
+
+impl BudgetReport {
+    /// Crates that exceed their budget
+    pub fn over_budget(&self) -> impl Iterator<Item = &BudgetEntry> {
+        self.crates.iter().filter(|entry| entry.is_over())
+    }
+}
+
+/// Compare per-crate totals (see `Tree::crate_totals`) with a crate -> max bytes budget
+///
+/// Crates without a budget are left out, crates with a budget that are missing from
+/// `totals` count as 0 bytes.
+pub fn check_budget(totals: &[(String, u64)], budget: &HashMap<String, u64>) -> BudgetReport {
+    let totals: HashMap<&str, u64> = totals.iter().map(|(name, b)| (name.as_str(), *b)).collect();
+    let mut crates: Vec<BudgetEntry> = budget
+        .iter()
+        .map(|(name, &max)| {
+            let bytes = totals.get(name.as_str()).copied().unwrap_or(0);
+            BudgetEntry {
+                name: name.clone(),
+                bytes,
+                budget: max,
+                delta: bytes as i64 - max as i64,
+            }
+        })
+        .collect();
+    crates.sort_by(|a, b| b.delta.cmp(&a.delta).then_with(|| a.name.cmp(&b.name)));
+    BudgetReport { crates }
+}
+
+/// Size of a crate as a multiple of the reference crate's size
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RelativeEntry {
+    pub name: String,
+    pub bytes: u64,
+    pub ratio: f64,
+}
+
+/// Per-crate sizes relative to a reference crate, see [`relative_to_crate`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RelativeReport {
+    pub reference: String,
+    pub reference_bytes: u64,
+    /// All crates, sorted by size (largest first), then by name
+    pub crates: Vec<RelativeEntry>,
+}
+
+/// Divide per-crate totals (see `Tree::crate_totals`) by the total of the `reference` crate
+///
+/// Fails if `reference` is not one of the crates in `totals`.
+pub fn relative_to_crate(totals: &[(String, u64)], reference: &str) -> Result<RelativeReport> {
+    let reference_bytes = totals
+        .iter()
+        .find(|(name, _)| name == reference)
+        .map(|(_, bytes)| *bytes)
+        .ok_or_else(|| BloatyError::NodeNotFound {
+            name: reference.to_string(),
+        })?;
+    let mut crates: Vec<RelativeEntry> = totals
+        .iter()
+        .map(|(name, bytes)| RelativeEntry {
+            name: name.clone(),
+            bytes: *bytes,
+            ratio: *bytes as f64 / reference_bytes as f64,
+        })
+        .collect();
+    crates.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+    Ok(RelativeReport {
+        reference: reference.to_string(),
+        reference_bytes,
+        crates,
+    })
+}
+
+/// Format a signed byte delta, with a human readable unit if `human` is set
+fn format_delta(delta: i64, human: bool) -> String {
+    match (human, delta < 0) {
+        (false, _) => format!("{delta:+}"),
+        (true, false) => format!("+{}", format_size(delta.unsigned_abs())),
+        (true, true) => format!("-{}", format_size(delta.unsigned_abs())),
+    }
+}
+
+/// One line per added, removed and changed input, then the total
+impl fmt::Display for MetafileDiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let human = f.alternate();
+        for (label, list) in [
+            ("added", &self.added),
+            ("removed", &self.removed),
+            ("changed", &self.changed),
+        ] {
+            for d in list {
+                writeln!(
+                    f,
+                    "{label:>8} {} ({} -> {}) {}",
+                    format_delta(d.delta, human),
+                    size_cell(d.base_bytes, human),
+                    size_cell(d.current_bytes, human),
+                    d.path
+                )?;
+            }
+        }
+        let total = if human {
+            format_delta(self.total_delta, true)
+        } else {
+            format!("{:+} bytes", self.total_delta)
+        };
+        write!(
+            f,
+            "total: {total} ({} added, {} removed, {} changed)",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len()
+        )
+    }
+}
+
+/// A `crate old new delta` table
+impl fmt::Display for CrateDiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let human = f.alternate();
+        let rows: Vec<[String; 4]> = self
+            .crates
+            .iter()
+            .map(|d| {
+                [
+                    d.path.clone(),
+                    size_cell(d.base_bytes, human),
+                    size_cell(d.current_bytes, human),
+                    format_delta(d.delta, human),
+                ]
+            })
+            .collect();
+        write_table(f, ["crate", "old", "new", "delta"], &rows)?;
+        write!(
+            f,
+            "total: {} ({} crates changed)",
+            format_delta(self.total_delta, human),
+            self.crates.len()
+        )
+    }
+}
+
+/// A `crate size budget delta` table
+impl fmt::Display for BudgetReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let human = f.alternate();
+        let rows: Vec<[String; 4]> = self
+            .crates
+            .iter()
+            .map(|entry| {
+                [
+                    entry.name.clone(),
+                    size_cell(entry.bytes, human),
+                    size_cell(entry.budget, human),
+                    format_delta(entry.delta, human),
+                ]
+            })
+            .collect();
+        write_table(f, ["crate", "size", "budget", "delta"], &rows)?;
+        write!(
+            f,
+            "{} of {} crates over budget",
+            self.over_budget().count(),
+            self.crates.len()
+        )
+    }
+}
+
+/// A `crate size ratio` table
+impl fmt::Display for RelativeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let human = f.alternate();
+        let rows: Vec<[String; 3]> = self
+            .crates
+            .iter()
+            .map(|entry| {
+                [
+                    entry.name.clone(),
+                    size_cell(entry.bytes, human),
+                    format!("{:.2}x", entry.ratio),
+                ]
+            })
+            .collect();
+        write_table(f, ["crate", "size", "ratio"], &rows)?;
+        write!(
+            f,
+            "relative to {} ({})",
+            self.reference,
+            size_cell(self.reference_bytes, human)
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_budget, diff_crates, diff_metafiles, relative_to_crate};
+    use crate::BloatyError;
+    use serde_metafile::Metafile;
+    use std::collections::HashMap;
+
+    fn metafile(inputs: &[(&str, u64)]) -> Metafile {
+        let inputs = inputs
+            .iter()
+            .map(|(path, bytes)| format!(r#""{path}":{{"bytes":{bytes},"imports":[]}}"#))
+            .collect::<Vec<_>>()
+            .join(",");
+        serde_json::from_str(&format!(r#"{{"inputs":{{{inputs}}},"outputs":{{}}}}"#))
+            .expect("Failed to parse metafile")
+    }
+
+    #[test]
+    fn test_diff_metafiles() {
+        let base = metafile(&[("a", 10), ("b", 20), ("c", 30)]);
+        let current = metafile(&[("a", 10), ("b", 25), ("d", 5)]);
+        let report = diff_metafiles(&base, &current);
+
+        assert_eq!(report.added.len(), 1);
+        assert_eq!(report.added[0].path, "d");
+        assert_eq!(report.added[0].delta, 5);
+
+        assert_eq!(report.removed.len(), 1);
+        assert_eq!(report.removed[0].path, "c");
+        assert_eq!(report.removed[0].delta, -30);
+
+        assert_eq!(report.changed.len(), 1);
+        assert_eq!(report.changed[0].path, "b");
+        assert_eq!(report.changed[0].delta, 5);
+
+        assert_eq!(report.total_delta, -20);
+        assert!(diff_metafiles(&base, &base).is_empty());
+    }
+
+    #[test]
+    fn test_diff_crates() {
+        let totals = |crates: &[(&str, u64)]| -> Vec<(String, u64)> {
+            crates.iter().map(|(n, b)| (n.to_string(), *b)).collect()
+        };
+        let base = totals(&[("foo", 100), ("bar", 200), ("gone", 5)]);
+        let current = totals(&[("foo", 100), ("bar", 260), ("new", 10)]);
+        let report = diff_crates(&base, &current);
+
+        let names: Vec<_> = report.crates.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(names, vec!["bar", "new", "gone"]);
+        assert_eq!(report.crates[0].delta, 60);
+        assert_eq!(report.total_delta, 65);
+        assert_eq!(
+            report.to_string(),
+            concat!(
+                "crate  old  new  delta\n",
+                "bar    200  260    +60\n",
+                "new      0   10    +10\n",
+                "gone     5    0     -5\n",
+                "total: +65 (3 crates changed)"
+            )
+        );
+    }
+
+    #[test]
+    fn test_check_budget() {
+        let totals = vec![("foo".to_string(), 120), ("bar".to_string(), 50)];
+        let budget = HashMap::from([
+            ("foo".to_string(), 100),
+            ("bar".to_string(), 80),
+            ("gone".to_string(), 10),
+        ]);
+        let report = check_budget(&totals, &budget);
+
+        let over: Vec<_> = report.over_budget().map(|e| e.name.as_str()).collect();
+        assert_eq!(over, vec!["foo"]);
+        assert_eq!(
+            report.to_string(),
+            concat!(
+                "crate  size  budget  delta\n",
+                "foo     120     100    +20\n",
+                "gone      0      10    -10\n",
+                "bar      50      80    -30\n",
+                "1 of 3 crates over budget"
+            )
+        );
+
+        let budget = HashMap::from([("foo".to_string(), 200)]);
+        assert_eq!(check_budget(&totals, &budget).over_budget().count(), 0);
+    }
+
+    #[test]
+    fn test_relative_to_crate() {
+        let totals = vec![
+            ("clap".to_string(), 100),
+            ("serde".to_string(), 320),
+            ("tiny".to_string(), 5),
+        ];
+        let report = relative_to_crate(&totals, "clap").expect("reference crate");
+        assert_eq!(report.reference_bytes, 100);
+        let ratios: Vec<_> = report
+            .crates
+            .iter()
+            .map(|e| (e.name.as_str(), e.ratio))
+            .collect();
+        assert_eq!(ratios, vec![("serde", 3.2), ("clap", 1.0), ("tiny", 0.05)]);
+        assert_eq!(
+            report.to_string(),
+            concat!(
+                "crate  size  ratio\n",
+                "serde   320  3.20x\n",
+                "clap    100  1.00x\n",
+                "tiny      5  0.05x\n",
+                "relative to clap (100)"
+            )
+        );
+
+        assert!(matches!(
+            relative_to_crate(&totals, "missing"),
+            Err(BloatyError::NodeNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_display_human() {
+        let base = metafile(&[("a", 1024), ("b", 2048)]);
+        let current = metafile(&[("a", 3 << 20)]);
+        let report = diff_metafiles(&base, &current);
+
+        assert_eq!(
+            format!("{report}"),
+            concat!(
+                " removed -2048 (2048 -> 0) b\n",
+                " changed +3144704 (1024 -> 3145728) a\n",
+                "total: +3142656 bytes (0 added, 1 removed, 1 changed)"
+            )
+        );
+        assert_eq!(
+            format!("{report:#}"),
+            concat!(
+                " removed -2.0 KB (2.0 KB -> 0 B) b\n",
+                " changed +3.0 MB (1.0 KB -> 3.0 MB) a\n",
+                "total: +3.0 MB (0 added, 1 removed, 1 changed)"
+            )
+        );
+    }
+}
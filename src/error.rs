@@ -11,6 +11,36 @@ pub enum BloatyError {
         source: std::io::Error,
     },
 
+    /// Error writing a file to the filesystem
+    #[error("Failed to write file: {path}")]
+    FileWrite {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// CSV input contains no data records
+    #[error("CSV input contains no data records")]
+    EmptyInput,
+
+    /// CSV header doesn't match any known bloaty column layout
+    #[error(
+        "Unrecognized bloaty CSV header `{header}`, expected the columns `{expected}` (bloaty -d sections,symbols --csv)"
+    )]
+    UnrecognizedFormat { header: String, expected: String },
+
+    /// No node with the given name in the tree, e.g. for `--root-at`
+    #[error("No crate or node named `{name}` in the tree")]
+    NodeNotFound { name: String },
+
+    /// Error decompressing gzip input
+    #[error("Failed to decompress gzip input: {path}")]
+    Decompression {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
     /// Error parsing CSV data
     #[error("Failed to parse CSV")]
     CsvParse(#[from] csv::Error),
@@ -19,6 +49,58 @@ pub enum BloatyError {
     #[error("Failed to serialize JSON")]
     JsonSerialize(#[from] serde_json::Error),
 
+    /// Error parsing a metafile JSON, e.g. a baseline for `--relative-to`
+    #[error("Failed to parse metafile: {path}")]
+    MetafileParse {
+        path: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    /// Error parsing a JSON configuration file, e.g. `--feature-map`
+    #[error("Failed to parse JSON file: {path}")]
+    JsonParse {
+        path: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    /// Invalid `--symbol-regex` rule
+    #[error("Invalid symbol regex `{rule}`: {reason}")]
+    InvalidSymbolRegex { rule: String, reason: String },
+
+    /// `bloaty` is not installed or not on `PATH`, for `--bin`
+    #[error(
+        "bloaty not found, install it from https://github.com/google/bloaty and add it to PATH"
+    )]
+    BloatyNotFound,
+
+    /// Running `bloaty` failed, for `--bin`
+    #[error("bloaty failed: {message}")]
+    BloatyFailed { message: String },
+
+    /// Crates exceed their size budget, see `--budget`
+    #[error("Crates over budget: {}", crates.join(", "))]
+    OverBudget { crates: Vec<String> },
+
+    /// Error listening on or writing to a TCP address, for `--listen`
+    #[error("Failed to send output to {addr}")]
+    Connection {
+        addr: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// `--format versions` only reads the lockfile, so it can't be combined with `--no-lock`
+    #[error("--format versions lists the crates in Cargo.lock and can't be used with --no-lock")]
+    VersionsWithoutLockfile,
+
+    /// Few crates of the CSV are in Cargo.lock, with `strict`
+    #[error(
+        "Only {matched} of {total} crates in the CSV are in the lockfile, is it the lockfile of this binary?"
+    )]
+    LockfileMismatch { matched: usize, total: usize },
+
     /// Error loading Cargo.lock file
     #[error("Failed to load Cargo.lock: {path}")]
     LockfileLoad {
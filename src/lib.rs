@@ -1,12 +1,36 @@
 use serde_metafile::Metafile;
-use tree::Tree;
+use std::collections::HashSet;
 
+mod color;
+mod converter;
+mod diff;
 mod error;
+mod options;
 mod packages;
+#[cfg(feature = "pprof")]
+mod pprof;
+mod report;
+mod rewrite;
+mod summary;
+mod timings;
 mod tool;
 mod tree;
 
+pub use color::{ColorChoice, Style};
+pub use converter::Converter;
+pub use diff::{
+    BudgetEntry, BudgetReport, CrateDiffReport, InputDelta, MetafileDiffReport, RelativeEntry,
+    RelativeReport, check_budget, diff_crates, diff_metafiles, relative_to_crate,
+};
 pub use error::{BloatyError, Result};
+pub use options::{ConvertOptions, PathStrategy, Weight};
+pub use packages::{Packages, versions_report};
+pub use report::{CrateStats, CrateVersion, StatsReport, VersionsReport, format_size, to_html};
+pub use rewrite::{MergeStd, PathRewriter, SymbolRegex};
+pub use summary::{InputSummary, input_id, summarize_inputs};
+pub use timings::Timings;
+pub use tool::resolve_symbol;
+pub use tree::{Node, SectionRecord, SymbolResolution, Tree, parse_section_sizes};
 
 /// Convert bloaty CSV output to esbuild metafile format
 ///
@@ -38,6 +62,405 @@ pub fn from_csv(
     deep: usize,
     no_sections: bool,
 ) -> Result<Metafile> {
-    let tree = Tree::new(csv, lock, no_sections)?;
-    Ok(tree.to_metafile(name, deep))
+    let options = ConvertOptions {
+        lock,
+        deep,
+        no_sections,
+        ..Default::default()
+    };
+    from_csv_with_options(csv, name, &options)
+}
+
+/// Convert bloaty CSV output to esbuild metafile format using [`ConvertOptions`]
+///
+/// # Example
+///
+/// ```no_run
+/// use bloaty_metafile::{ConvertOptions, from_csv_with_options};
+///
+/// let csv = "sections,symbols,vmsize,filesize\n.text,main,1000,1000";
+/// let options = ConvertOptions {
+///     deep: 4,
+///     ..Default::default()
+/// };
+/// let metafile = from_csv_with_options(csv, "binary", &options)?;
+/// # Ok::<(), bloaty_metafile::BloatyError>(())
+/// ```
+pub fn from_csv_with_options(csv: &str, name: &str, options: &ConvertOptions) -> Result<Metafile> {
+    let tree = Tree::new(csv, options)?;
+    Ok(tree.to_metafile(name, options))
+}
+
+/// Convert already parsed (or programmatically built) records to esbuild metafile format
+///
+/// # Example
+///
+/// ```no_run
+/// use bloaty_metafile::{ConvertOptions, SectionRecord, from_records};
+///
+/// let records = vec![SectionRecord {
+///     sections: ".text".to_string(),
+///     symbols: "main".to_string(),
+///     vmsize: 1000,
+///     filesize: 1000,
+///     ..Default::default()
+/// }];
+/// let metafile = from_records(records, "binary", &ConvertOptions::default())?;
+/// # Ok::<(), bloaty_metafile::BloatyError>(())
+/// ```
+pub fn from_records(
+    records: Vec<SectionRecord>,
+    name: &str,
+    options: &ConvertOptions,
+) -> Result<Metafile> {
+    let tree = Tree::from_records(records, options)?;
+    Ok(tree.to_metafile(name, options))
+}
+
+/// Like [`from_records`], resolving crates with an already built dependency tree instead of
+/// loading a Cargo.lock file
+pub fn from_records_with_tree(
+    records: Vec<SectionRecord>,
+    dep_tree: &cargo_lock::dependency::Tree,
+    name: &str,
+    options: &ConvertOptions,
+) -> Result<Metafile> {
+    let tree = Tree::from_records_with_tree(records, dep_tree, options)?;
+    Ok(tree.to_metafile(name, options))
+}
+
+/// Convert bloaty CSV output to one standalone metafile per top-level crate
+///
+/// Returns `(crate name, metafile)` pairs sorted by name, each metafile having a
+/// single output named after its crate. Useful when the combined metafile is too large.
+pub fn from_csv_by_crate(csv: &str, options: &ConvertOptions) -> Result<Vec<(String, Metafile)>> {
+    let tree = Tree::new(csv, options)?;
+    Ok(tree.to_crate_metafiles(options))
+}
+
+/// Serialize a metafile to a JSON string with a stable key order
+///
+/// `Metafile.inputs` and `Metafile.outputs` are `HashMap`s, so serializing them directly
+/// gives a different order on every run. The metafile is converted to a `serde_json::Value`
+/// first, whose objects are sorted by key, so the same metafile always gives the same string.
+pub fn to_json_string(meta: &Metafile) -> Result<String> {
+    let value = serde_json::to_value(meta)?;
+    Ok(serde_json::to_string(&value)?)
+}
+
+/// Write a metafile as newline-delimited JSON, for consumers that stream-parse large outputs
+///
+/// Each input is one line `{"bytes":N,"imports":["path",...],"path":"..."}`, sorted by path,
+/// followed by one last line `{"outputs":{...}}` with the metafile's outputs.
+pub fn write_ndjson(meta: &Metafile, mut writer: impl std::io::Write) -> Result<()> {
+    let mut inputs: Vec<_> = meta.inputs.iter().collect();
+    inputs.sort_by(|a, b| a.0.cmp(b.0));
+    for (path, input) in inputs {
+        let imports: Vec<&str> = input.imports.iter().map(|i| i.path.as_str()).collect();
+        let line = serde_json::json!({
+            "path": path,
+            "bytes": input.bytes,
+            "imports": imports,
+        });
+        serde_json::to_writer(&mut writer, &line)?;
+        writer.write_all(b"\n").map_err(serde_json::Error::io)?;
+    }
+    let line = serde_json::json!({ "outputs": serde_json::to_value(&meta.outputs)? });
+    serde_json::to_writer(&mut writer, &line)?;
+    writer.write_all(b"\n").map_err(serde_json::Error::io)?;
+    writer.flush().map_err(serde_json::Error::io)?;
+    Ok(())
+}
+
+/// Serialize the tree's metafile with the largest depth at which the JSON fits in `max_len` bytes
+///
+/// Starts from `options.deep`, and if that doesn't fit, searches for the largest smaller depth
+/// that does (a binary search, so the metafile is built and serialized a few times).
+/// Returns the metafile, its JSON and the depth used. If the JSON doesn't even fit at depth 1,
+/// the depth 1 result is returned, so check the length of the JSON.
+pub fn to_json_string_within(
+    tree: &Tree,
+    name: &str,
+    options: &ConvertOptions,
+    max_len: usize,
+) -> Result<(Metafile, String, usize)> {
+    let convert = |deep: usize| -> Result<(Metafile, String)> {
+        let options = ConvertOptions {
+            deep,
+            ..options.clone()
+        };
+        let meta = tree.to_metafile(name, &options);
+        let s = to_json_string(&meta)?;
+        Ok((meta, s))
+    };
+
+    let (meta, s) = convert(options.deep)?;
+    if s.len() <= max_len {
+        return Ok((meta, s, options.deep));
+    }
+
+    // A depth of max_depth or more is the same as unlimited
+    let max_depth = tree.max_depth();
+    let limit = match options.deep {
+        0 => max_depth,
+        deep => deep.min(max_depth),
+    };
+    // Largest depth in [1, limit) that fits, depth 1 if none does
+    let (mut lo, mut hi) = (1, limit.saturating_sub(1).max(1));
+    let mut best = None;
+    while lo <= hi {
+        let mid = lo + (hi - lo) / 2;
+        let (meta, s) = convert(mid)?;
+        if s.len() <= max_len {
+            best = Some((meta, s, mid));
+            lo = mid + 1;
+        } else {
+            hi = mid - 1;
+        }
+    }
+    match best {
+        Some(best) => Ok(best),
+        None => {
+            let (meta, s) = convert(1)?;
+            Ok((meta, s, 1))
+        }
+    }
+}
+
+/// Paths of the children of the input `path` in a metafile, in import order
+///
+/// An empty `path` returns the top-level inputs of the output `name` (the inputs that are
+/// not imported by another input), sorted. Unknown paths or outputs have no children.
+///
+/// # Example
+///
+/// ```no_run
+/// use bloaty_metafile::{children_of, from_csv};
+///
+/// let csv = "sections,symbols,vmsize,filesize\n.text,foo::bar,1000,1000";
+/// let meta = from_csv(csv, "BINARY", None, 0, false)?;
+/// assert_eq!(children_of(&meta, "BINARY", "foo"), vec!["foo/.text"]);
+/// # Ok::<(), bloaty_metafile::BloatyError>(())
+/// ```
+pub fn children_of<'a>(meta: &'a Metafile, name: &str, path: &str) -> Vec<&'a str> {
+    if !path.is_empty() {
+        return meta.inputs.get(path).map_or_else(Vec::new, |input| {
+            input
+                .imports
+                .iter()
+                .map(|import| import.path.as_str())
+                .collect()
+        });
+    }
+
+    let Some(output) = meta.outputs.get(name) else {
+        return vec![];
+    };
+    let imported: HashSet<&str> = meta
+        .inputs
+        .values()
+        .flat_map(|input| input.imports.iter().map(|import| import.path.as_str()))
+        .collect();
+    let mut top: Vec<&str> = output
+        .inputs
+        .keys()
+        .map(String::as_str)
+        .filter(|path| !imported.contains(path))
+        .collect();
+    top.sort_unstable();
+    top
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        ConvertOptions, Tree, children_of, from_csv, from_csv_with_options, from_records_with_tree,
+        packages::test::{dependency_tree, records},
+        to_json_string, to_json_string_within, write_ndjson,
+    };
+    use serde_metafile::Metafile;
+
+    #[test]
+    fn test_write_ndjson() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,10,10
+.text,foo::b,20,20
+"#;
+        let meta = from_csv(csv, "BINARY", None, 0, false).expect("Failed to convert");
+        let mut out = Vec::new();
+        write_ndjson(&meta, &mut out).expect("Failed to write");
+        let out = String::from_utf8(out).expect("utf8");
+
+        let lines: Vec<serde_json::Value> = out
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("Each line is valid JSON"))
+            .collect();
+        assert_eq!(lines.len(), meta.inputs.len() + 1);
+        let (outputs, inputs) = lines.split_last().expect("outputs line");
+        assert_eq!(outputs["outputs"]["BINARY"]["bytes"], 30);
+        let a = inputs
+            .iter()
+            .find(|line| line["path"] == "foo/.text/a")
+            .expect("input line");
+        assert_eq!(a["bytes"], 10);
+        assert_eq!(a["imports"], serde_json::json!([]));
+        let foo = inputs
+            .iter()
+            .find(|line| line["path"] == "foo")
+            .expect("input line");
+        assert_eq!(foo["imports"], serde_json::json!(["foo/.text"]));
+    }
+
+    #[test]
+    fn test_from_records_with_tree() {
+        let dep_tree = dependency_tree(&[
+            ("app", "0.1.0", &["foo", "bar"]),
+            ("foo", "1.0.0", &["bar"]),
+            ("bar", "1.0.0", &[]),
+        ]);
+        // No lockfile is loaded, the dependency tree is used instead
+        let options = ConvertOptions::default();
+        let meta = from_records_with_tree(
+            records(&["app::main", "foo::a", "bar::b"]),
+            &dep_tree,
+            "BINARY",
+            &options,
+        )
+        .expect("Failed to convert");
+        assert_eq!(meta.inputs["app/foo/.text/a"].bytes, 1);
+        assert_eq!(meta.inputs["app/bar/.text/b"].bytes, 1);
+        assert_eq!(meta.outputs["BINARY"].bytes, 3);
+    }
+
+    #[test]
+    fn test_to_json_string_is_sorted() {
+        let output = r#"{"bytes":0,"inputs":{},"imports":[],"exports":[]}"#;
+        let json =
+            format!(r#"{{"inputs":{{}},"outputs":{{"c":{output},"a":{output},"b":{output}}}}}"#);
+        let meta: Metafile = serde_json::from_str(&json).expect("Failed to parse metafile");
+        let s = to_json_string(&meta).expect("Failed to serialize");
+        let pos = |name: &str| s.find(&format!(r#""{name}":{{"#)).expect("output exists");
+        assert!(pos("a") < pos("b") && pos("b") < pos("c"), "{s}");
+        assert_eq!(s, to_json_string(&meta).expect("Failed to serialize"));
+    }
+
+    #[test]
+    fn test_to_json_string_within() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a::b::c::d,1,1
+.text,foo::a::b::e::f,2,2
+.text,bar::g,4,4
+"#;
+        let options = ConvertOptions::default();
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        let len = |deep: usize| {
+            let options = ConvertOptions {
+                deep,
+                ..Default::default()
+            };
+            to_json_string(&tree.to_metafile("BINARY", &options))
+                .expect("Failed to serialize")
+                .len()
+        };
+
+        // Fits without limiting the depth
+        let (_, s, deep) =
+            to_json_string_within(&tree, "BINARY", &options, usize::MAX).expect("Failed");
+        assert_eq!((s.len(), deep), (len(0), 0));
+
+        // The largest depth that fits
+        for budget_deep in 1..4 {
+            let budget = len(budget_deep);
+            let (meta, s, deep) =
+                to_json_string_within(&tree, "BINARY", &options, budget).expect("Failed");
+            assert_eq!(deep, budget_deep);
+            assert!(s.len() <= budget);
+            assert_eq!(meta.outputs["BINARY"].bytes, 7);
+        }
+
+        // Too small for any depth
+        let (_, _, deep) = to_json_string_within(&tree, "BINARY", &options, 1).expect("Failed");
+        assert_eq!(deep, 1);
+    }
+
+    #[test]
+    fn test_children_of() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,1,1
+.text,foo::b::c,2,2
+.rodata,foo::d,4,4
+.text,bar::e,8,8
+"#;
+        let options = ConvertOptions {
+            no_lock: true,
+            ..Default::default()
+        };
+        let meta = from_csv_with_options(csv, "BINARY", &options).expect("Failed to convert");
+        assert_eq!(children_of(&meta, "BINARY", ""), vec!["bar", "foo"]);
+        let mut sections = children_of(&meta, "BINARY", "foo");
+        sections.sort_unstable();
+        assert_eq!(sections, vec!["foo/.rodata", "foo/.text"]);
+        let mut symbols = children_of(&meta, "BINARY", "foo/.text");
+        symbols.sort_unstable();
+        assert_eq!(symbols, vec!["foo/.text/a", "foo/.text/b"]);
+        assert!(children_of(&meta, "BINARY", "foo/.text/a").is_empty());
+        assert!(children_of(&meta, "BINARY", "missing").is_empty());
+        assert!(children_of(&meta, "OTHER", "").is_empty());
+    }
+
+    #[test]
+    fn test_total_size_conservation() {
+        let fixtures = [
+            // Mach-O segment,section syntax with an aggregated remainder row
+            r#"
+sections,symbols,vmsize,filesize
+"__TEXT,__text",[1848 Others],918108,918108
+"__TEXT,__text",foo::bar::baz,100,100
+"__DATA,__data",foo::DATA,0,24
+"#,
+            // ELF with an aggregated remainder row
+            r#"
+sections,symbols,vmsize,filesize
+.text,[1843 Others],1086372,1086372
+.text,main,10,10
+.rodata,,5,5
+"#,
+            // Angle bracket symbols and closures
+            r#"
+sections,symbols,vmsize,filesize
+.text,<&core::alloc::layout::Layout as core::fmt::Debug>::fmt,30,30
+.text,<u8 as <[_]>::to_vec_in::ConvertVec>::to_vec::<>,40,40
+.text,std::sys::backtrace::_print_fmt::{closure#1}::{closure#0},50,50
+"#,
+            // A node that is both a leaf and an intermediate node
+            r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,1,1
+.text,foo::a::b,2,2
+.text,foo::a::b::c,4,4
+.text,foo::a::b::c,8,8
+"#,
+        ];
+
+        for csv in fixtures {
+            for deep in [0, 1, 2, 1000] {
+                let meta = from_csv(csv, "BINARY", None, deep, false).expect("Failed to convert");
+                let output = &meta.outputs["BINARY"];
+                let inputs_total: u64 = meta.inputs.values().map(|input| input.bytes).sum();
+                let output_inputs_total: u64 = output
+                    .inputs
+                    .values()
+                    .map(|detail| detail.bytes_in_output)
+                    .sum();
+                assert_eq!(inputs_total, output.bytes, "deep: {deep}, csv: {csv}");
+                assert_eq!(
+                    output_inputs_total, output.bytes,
+                    "deep: {deep}, csv: {csv}"
+                );
+            }
+        }
+    }
 }
@@ -1,75 +1,1095 @@
-use bloaty_metafile::{BloatyError, from_csv};
-use clap::Parser;
+use bloaty_metafile::{
+    BloatyError, ColorChoice, ConvertOptions, PathStrategy, Style, SymbolRegex, Timings, Tree,
+    Weight, check_budget, diff_crates, diff_metafiles, parse_section_sizes, summarize_inputs,
+    to_html, to_json_string, to_json_string_within, versions_report, write_ndjson,
+};
+use cargo_lock::Lockfile;
+use clap::{Parser, ValueEnum};
+use flate2::read::MultiGzDecoder;
+use serde_metafile::Metafile;
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Read, Write},
+    process::ExitCode,
+    time::{Duration, Instant},
+};
 
 const CARGO_PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
 const GIT_HASH: &str = git_version::git_version!();
 const VERSION: &str = const_str::concat!(CARGO_PKG_VERSION, " ", GIT_HASH);
 
+// Check if JSON string is too large (JavaScript string length limit)
+// JavaScript max string length is 2^30 - 1 (0x3fffffff) characters
+// But V8 uses 0x1fffffe8 as practical limit
+const MAX_JSON_LENGTH: usize = 0x1fff_ffe8; // ~536MB
+
+/// Magic bytes at the start of gzip data
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Output format
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// esbuild metafile
+    #[default]
+    Metafile,
+    /// Recursive `{name, vmsize, filesize, children}` tree
+    Nested,
+    /// Self-contained HTML treemap
+    Html,
+    /// Recursive `{name, data: {"$area": size}, children}` tree for webtreemap
+    Webtreemap,
+    /// JSON list of the metafile's inputs as `{path, id, bytes}`, with a stable numeric id
+    Summary,
+    /// One `{path, bytes, imports}` JSON line per input, then a `{outputs}` line
+    Ndjson,
+    /// Table of every crate in Cargo.lock with its version, source and duplicate majors,
+    /// no CSV is read
+    Versions,
+    /// Gzip compressed pprof profile, for pprof, speedscope and other flamegraph tools
+    #[cfg(feature = "pprof")]
+    Pprof,
+}
+
 #[derive(Parser, Debug, Clone)]
 #[command(version=VERSION, about, long_about = None)]
 pub struct Args {
-    #[arg(short, long, default_value = "BINARY")]
+    #[arg(short, long, env = "BLOATY_METAFILE_NAME", default_value = "BINARY")]
     pub name: String,
 
-    #[arg(short, long)]
+    #[arg(short, long, env = "BLOATY_METAFILE_LOCK")]
     pub lock: Option<String>,
 
+    /// Fail instead of warning if few crates of the CSV are in the lockfile
+    #[arg(long, default_value = "false")]
+    pub strict: bool,
+
+    /// Don't load any Cargo.lock, every crate is shown as a top-level node, overrides --lock
+    #[arg(long, default_value = "false")]
+    pub no_lock: bool,
+
+    /// Which dependency path to place a crate under when several lead to it
+    #[arg(long, value_enum, default_value_t = PathStrategy::Shortest)]
+    pub path_strategy: PathStrategy,
+
+    /// Label crates with several incompatible versions in Cargo.lock by major version (`rand v0.8`)
+    #[arg(long, default_value = "false")]
+    pub group_by_version_major: bool,
+
+    /// Group crates by where they come from (REGISTRY, GIT, LOCAL) as an extra top level
+    #[arg(long, default_value = "false")]
+    pub group_by_source: bool,
+
+    /// Group crates by the direct dependency of the root that pulls them in, as the top level
+    #[arg(long, default_value = "false")]
+    pub group_by_direct_dep: bool,
+
     #[arg(short, long, default_value = "0")]
     pub deep: usize,
 
+    /// Depth limit for a single top-level crate, e.g. `std=1` (repeatable), overrides --deep
+    #[arg(long, value_parser = parse_key_value::<usize>)]
+    pub deep_override: Vec<(String, usize)>,
+
+    /// Lower --deep until the JSON output fits in this many bytes, the depth used is printed to stderr
+    #[arg(long, conflicts_with = "root_at")]
+    pub max_bytes: Option<usize>,
+
+    /// Lower --deep until the JSON output fits in the esbuild analyzer (JavaScript's string limit)
+    #[arg(long, default_value = "false", conflicts_with_all = ["root_at", "max_bytes"])]
+    pub fit_analyzer: bool,
+
+    /// Like --deep, but nodes past the depth are kept (with zero size) for navigation
+    #[arg(long, default_value = "0")]
+    pub collapse_after: usize,
+
+    /// Keep only the N largest children of each node, the rest are folded into `[others]`
+    #[arg(long, default_value = "0")]
+    pub max_children: usize,
+
+    /// Emit at most N inputs, the remaining nodes are folded into `[overflow]`
+    #[arg(long, default_value = "0")]
+    pub max_inputs: usize,
+
+    /// What the metafile sizes measure: file size in bytes, vm size in bytes, or the number of symbols
+    #[arg(long, value_enum, default_value_t = Weight::Bytes)]
+    pub weight: Weight,
+
+    /// What the output's bytesInOutput measure, defaults to --weight
+    #[arg(long, value_enum)]
+    pub output_weight: Option<Weight>,
+
     #[arg(long, default_value = "false")]
     pub no_sections: bool,
 
+    /// Merge sibling symbols that only differ in their generic arguments (`f<A>`, `f<B>` -> `f<_>`)
+    #[arg(long, default_value = "false")]
+    pub merge_generic_siblings: bool,
+
+    /// Leave out nodes whose size (measured by --weight) is zero, e.g. symbols only in vm memory
+    #[arg(long, default_value = "false")]
+    pub prune_empty: bool,
+
+    /// Only keep symbols in these sections, e.g. `.text,.rodata`
+    #[arg(long, value_delimiter = ',')]
+    pub only_sections: Vec<String>,
+
+    /// Stop at the section level, each crate's section carries the sum of its symbols
+    #[arg(long, default_value = "false")]
+    pub sections_as_leaves: bool,
+
+    /// Leave out the section level (`.text`, `.rodata`) below crates, symbols nest under their module
+    #[arg(long, default_value = "false")]
+    pub flatten_sections: bool,
+
+    /// Append the section to the symbol name (`symbol [.text]`) instead of a section level
+    #[arg(long, default_value = "false")]
+    pub section_suffix: bool,
+
+    /// Use canonical section names (`text`, `rodata`, ...) across ELF, Mach-O and PE
+    #[arg(long, default_value = "false")]
+    pub section_canonical: bool,
+
+    /// Merge PE/COFF grouped sections (`.text$mn`, `.text$x`) into their section (`.text`)
+    #[arg(long, default_value = "false")]
+    pub merge_pe_sections: bool,
+
+    /// Leave the section level out for records with an empty section instead of showing them
+    /// under `[no-section]`
+    #[arg(long, default_value = "false")]
+    pub drop_empty_sections: bool,
+
+    /// Output of `bloaty -d sections --csv`, bytes of a section not covered by its symbols are
+    /// added as `SECTIONS/<section>/[padding]`
+    #[arg(long)]
+    pub section_sizes: Option<String>,
+
+    /// Add the kind of each symbol's section (code, rodata, data, bss, unwind) to the leaves of
+    /// --format nested
+    #[arg(long, default_value = "false")]
+    pub symbol_kinds: bool,
+
+    /// List the top-level inputs (crates) in the output's imports
+    #[arg(long, default_value = "false")]
+    pub emit_imports: bool,
+
+    /// Emit an empty top-level `inputs` map, the per-input sizes are only in `outputs`
+    #[arg(long, default_value = "false")]
+    pub outputs_only: bool,
+
+    #[arg(short, long, value_enum, default_value_t = Format::Metafile)]
+    pub format: Format,
+
+    /// Accept CSV input without any data records
+    #[arg(long, default_value = "false")]
+    pub allow_empty: bool,
+
+    /// The CSV has no header line, columns are read as sections,symbols,vmsize,filesize
+    #[arg(long, default_value = "false")]
+    pub no_header: bool,
+
+    /// Write the symbols that could not be attributed to a crate to this file, one per line
+    #[arg(long)]
+    pub dump_unattributed: Option<String>,
+
+    /// Write a `symbol,resolved_crate,resolved_path` CSV row for every unique symbol to this file
+    #[arg(long)]
+    pub dump_resolution: Option<String>,
+
+    /// Attribute slice methods like `<[alloc::string::String]>::concat` to the element type's crate
+    #[arg(long, default_value = "false")]
+    pub slice_element_crate: bool,
+
+    /// Group entry point and startup symbols (`main`, `_start`, `lang_start`) under ENTRY
+    #[arg(long, default_value = "false")]
+    pub entry_bucket: bool,
+
+    /// Group symbols of these module paths under RUNTIME, e.g. `tokio::runtime,std::thread`
+    /// (repeatable)
+    #[arg(long, value_delimiter = ',')]
+    pub runtime_prefix: Vec<String>,
+
+    /// A proc-macro crate (repeatable), its symbols are grouped under `[proc-macro]`
+    #[arg(long)]
+    pub proc_macro: Vec<String>,
+
+    /// Leave out the symbols of --proc-macro crates instead of grouping them
+    #[arg(long, default_value = "false", requires = "proc_macro")]
+    pub exclude_proc_macros: bool,
+
+    /// Rename a crate, e.g. `serde_vendored=serde` (repeatable), aliased crates are merged
+    #[arg(long, value_parser = parse_key_value::<String>)]
+    pub alias: Vec<(String, String)>,
+
+    /// Attribute C symbols to a crate, e.g. `openssl_*=openssl-sys` (repeatable), a trailing `*`
+    /// matches any suffix
+    #[arg(long, value_parser = parse_key_value::<String>)]
+    pub c_symbols: Vec<(String, String)>,
+
+    /// Attribute closures in generic code of another crate to the crate in its generic arguments
+    #[arg(long, default_value = "false")]
+    pub blame_closures: bool,
+
+    /// Attribute `core::iter` and `core::ops` symbols to the crate in their generic arguments
+    #[arg(long, default_value = "false")]
+    pub collapse_std_generics: bool,
+
+    /// Merge items that only differ in their `#N` disambiguator (`bar#2`, `bar#3`) into `bar`
+    #[arg(long, default_value = "false")]
+    pub strip_disambiguators: bool,
+
+    /// JSON file mapping module path prefixes (`tokio::net`) to feature names
+    #[arg(long)]
+    pub feature_map: Option<String>,
+
+    /// JSON file mapping crate name prefixes (`hyper`) to component names
+    #[arg(long)]
+    pub components: Option<String>,
+
+    /// Baseline metafile JSON to compare against, the diff is printed to stderr
+    #[arg(long)]
+    pub relative_to: Option<String>,
+
+    /// Older bloaty CSV to compare with, prints the crates that changed size instead of the metafile
+    #[arg(long)]
+    pub compare: Option<String>,
+
+    /// JSON file mapping crate names to max bytes, prints the crates over or under budget
+    /// instead of the metafile and fails if any crate is over budget
+    #[arg(long)]
+    pub budget: Option<String>,
+
+    /// Print each crate's size as a multiple of this crate's size instead of the metafile
+    #[arg(long)]
+    pub relative_to_crate: Option<String>,
+
+    /// Print the diff against --relative-to as JSON to stdout instead of the metafile
+    #[arg(long, default_value = "false", requires = "relative_to")]
+    pub diff_json: bool,
+
+    /// Only output the subtree of this crate, with the crate as the top level
+    #[arg(long)]
+    pub root_at: Option<String>,
+
+    /// Write one metafile per top-level crate into this directory, plus an index.json
+    #[arg(long, conflicts_with_all = ["listen", "pipe"])]
+    pub split_by_crate: Option<String>,
+
+    /// Serve the output to the first client connecting to this TCP address instead of stdout
+    #[arg(long, conflicts_with = "pipe")]
+    pub listen: Option<String>,
+
+    /// Write the output to this named pipe (FIFO) or file instead of stdout
+    #[arg(long)]
+    pub pipe: Option<String>,
+
+    /// Print sizes in text reports as `1.2 MB` instead of raw bytes, JSON output is unaffected
+    #[arg(long, default_value = "false")]
+    pub human: bool,
+
+    /// Increase verbosity, -v warns about crates placed unusually deep in the dependency tree,
+    /// -vv prints the attribution path of every record to stderr
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Rewrite symbols before they are split into a path, e.g. `s/^my_company_//` (repeatable,
+    /// applied in order)
+    #[arg(long)]
+    pub symbol_regex: Vec<SymbolRegex>,
+
+    /// Print the attribution path of symbols containing this substring to stderr
+    #[arg(long)]
+    pub trace_symbol: Option<String>,
+
+    /// Print the symbol count, size and average symbol size of each crate to stderr
+    #[arg(long, default_value = "false")]
+    pub stats: bool,
+
+    /// Print the duration of each phase (parse, lockfile, build, convert, serialize) to stderr
+    #[arg(long, default_value = "false")]
+    pub timings: bool,
+
+    /// When to color warnings, errors and report headers on stderr, `auto` respects `NO_COLOR`
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+
+    /// Never use colors on stderr, same as --color=never
+    #[arg(long, default_value = "false", conflicts_with = "color")]
+    pub no_color: bool,
+
+    /// Run bloaty on this binary instead of reading its CSV output
+    #[arg(long, conflicts_with = "path")]
+    pub bin: Option<String>,
+
+    /// bloaty dimensions used with --bin, e.g. `sections,compileunits,symbols`
+    #[arg(long, default_value = "sections,symbols", requires = "bin")]
+    pub dimensions: String,
+
     #[arg()]
     pub path: Option<String>,
 }
 
-fn main() -> Result<(), BloatyError> {
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let choice = if args.no_color {
+        ColorChoice::Never
+    } else {
+        args.color
+    };
+    let style = Style::stderr(choice);
+    match run(args, style) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            // Print the error with its sources, e.g. the io error of a file that can't be read
+            let mut message = e.to_string();
+            let mut source = std::error::Error::source(&e);
+            while let Some(e) = source {
+                message.push_str(&format!(": {e}"));
+                source = e.source();
+            }
+            eprintln!("{}", style.error(message));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args, style: Style) -> Result<(), BloatyError> {
     let Args {
         name,
         lock,
+        no_lock,
+        strict,
+        path_strategy,
+        group_by_version_major,
+        group_by_source,
+        group_by_direct_dep,
         deep,
+        deep_override,
+        max_bytes,
+        fit_analyzer,
+        collapse_after,
+        max_children,
+        max_inputs,
+        weight,
+        output_weight,
         path,
         no_sections,
-    } = Args::parse();
+        merge_generic_siblings,
+        prune_empty,
+        only_sections,
+        sections_as_leaves,
+        flatten_sections,
+        section_suffix,
+        section_canonical,
+        merge_pe_sections,
+        drop_empty_sections,
+        section_sizes,
+        emit_imports,
+        symbol_kinds,
+        outputs_only,
+        format,
+        allow_empty,
+        no_header,
+        dump_unattributed,
+        dump_resolution,
+        alias,
+        c_symbols,
+        slice_element_crate,
+        entry_bucket,
+        runtime_prefix,
+        proc_macro,
+        exclude_proc_macros,
+        blame_closures,
+        collapse_std_generics,
+        strip_disambiguators,
+        feature_map,
+        components,
+        relative_to,
+        compare,
+        budget,
+        relative_to_crate,
+        diff_json,
+        split_by_crate,
+        listen,
+        pipe,
+        root_at,
+        human,
+        verbose,
+        symbol_regex,
+        trace_symbol,
+        stats,
+        timings,
+        color: _,
+        no_color: _,
+        bin,
+        dimensions,
+    } = args;
 
-    // Read CSV input from file or stdin
-    let csv = if let Some(ref file_path) = path {
-        std::fs::read_to_string(file_path).map_err(|source| BloatyError::FileRead {
-            path: file_path.clone(),
-            source,
-        })?
-    } else {
-        std::io::read_to_string(std::io::stdin()).map_err(|source| BloatyError::FileRead {
-            path: "stdin".to_string(),
+    let sink = match (listen, pipe) {
+        (Some(addr), _) => Sink::Listen(addr),
+        (None, Some(path)) => Sink::Pipe(path),
+        (None, None) => Sink::Stdout,
+    };
+
+    // Crate inventory from the lockfile alone
+    if format == Format::Versions {
+        if no_lock {
+            return Err(BloatyError::VersionsWithoutLockfile);
+        }
+        let lock_path = lock.unwrap_or_else(|| "Cargo.lock".to_string());
+        let lockfile = Lockfile::load(&lock_path).map_err(|source| BloatyError::LockfileLoad {
+            path: lock_path,
             source,
-        })?
+        })?;
+        return sink.bytes(versions_report(&lockfile).to_string().as_bytes());
+    }
+
+    // Read CSV input from file or stdin, or run bloaty on the binary
+    let csv = match bin {
+        Some(bin) => run_bloaty(&bin, &dimensions)?,
+        None => read_input(path.as_deref())?,
     };
 
     // Parse CSV and generate metafile
-    let meta = from_csv(&csv, &name, lock, deep, no_sections)?;
+    let options = ConvertOptions {
+        lock,
+        no_lock,
+        strict,
+        deep,
+        deep_overrides: deep_override.into_iter().collect(),
+        collapse_after,
+        max_children,
+        max_inputs,
+        weight,
+        output_weight,
+        no_sections,
+        merge_generic_siblings,
+        prune_empty,
+        only_sections,
+        sections_as_leaves,
+        flatten_sections,
+        section_suffix,
+        section_canonical,
+        merge_pe_sections,
+        drop_empty_sections,
+        section_sizes: match section_sizes {
+            Some(path) => parse_section_sizes(&read_input(Some(&path))?)?,
+            None => Default::default(),
+        },
+        emit_imports,
+        symbol_kinds,
+        outputs_only,
+        allow_empty,
+        no_header,
+        collect_unattributed: dump_unattributed.is_some(),
+        collect_resolution: dump_resolution.is_some(),
+        path_strategy,
+        group_by_version_major,
+        group_by_source,
+        group_by_direct_dep,
+        aliases: alias.into_iter().collect(),
+        c_symbols: c_symbols.into_iter().collect(),
+        slice_element_crate,
+        entry_bucket,
+        runtime_prefixes: runtime_prefix,
+        proc_macros: proc_macro,
+        exclude_proc_macros,
+        blame_closures,
+        collapse_std_generics,
+        strip_disambiguators,
+        feature_map: match feature_map {
+            Some(path) => read_json(&path)?,
+            None => Default::default(),
+        },
+        components: match components {
+            Some(path) => read_json(&path)?,
+            None => Default::default(),
+        },
+        symbol_regex,
+        trace_symbol: trace_symbol.or_else(|| (verbose >= 2).then(String::new)),
+    };
+
+    let tree = Tree::new(&csv, &options)?;
+    // Printed when `run` returns, whatever the output
+    let mut phases = TimingsReport {
+        phases: tree.timings(),
+        enabled: timings,
+    };
+
+    if let Some(reason) = tree.lockfile_fallback() {
+        eprintln!(
+            "{}",
+            style.warning(format_args!(
+                "{reason}, every crate is shown as a top-level node (use --no-lock to silence this)"
+            ))
+        );
+    }
+
+    if let Some((matched, total)) = tree.lockfile_mismatch() {
+        eprintln!(
+            "{}",
+            style.warning(format_args!(
+                "only {matched} of {total} crates in the CSV are in the lockfile, is it the lockfile of this binary?"
+            ))
+        );
+    }
+
+    if tree.skipped_records() > 0 {
+        eprintln!(
+            "{}",
+            style.warning(format_args!(
+                "skipped {} CSV rows with missing fields",
+                tree.skipped_records()
+            ))
+        );
+    }
+
+    if stats {
+        let report = report_text(&tree.crate_stats(), human);
+        eprint!("{}", style.table(&report));
+    }
+
+    for (section, symbol, path) in tree.traces() {
+        eprintln!("trace: ({section}, {symbol}) -> {path}");
+    }
+
+    if verbose >= 1 {
+        for warning in tree.depth_warnings() {
+            eprintln!("{}", style.warning(warning));
+        }
+    }
+
+    if let Some(file) = dump_unattributed {
+        let s: String = tree
+            .unattributed()
+            .iter()
+            .map(|s| format!("{s}\n"))
+            .collect();
+        std::fs::write(&file, s).map_err(|source| BloatyError::FileWrite { path: file, source })?;
+    }
+
+    if let Some(file) = dump_resolution {
+        let s = tree.resolution_csv()?;
+        std::fs::write(&file, s).map_err(|source| BloatyError::FileWrite { path: file, source })?;
+    }
+
+    // Per crate size changes against an older CSV
+    if let Some(old_path) = compare {
+        let old = Tree::new(&read_input(Some(&old_path))?, &options)?;
+        let report = diff_crates(
+            &old.crate_totals(options.weight),
+            &tree.crate_totals(options.weight),
+        );
+        return sink.text(&report_text(&report, human));
+    }
+
+    // Per crate sizes against a budget, as a CI size gate
+    if let Some(budget_path) = budget {
+        let budget: HashMap<String, u64> = read_json(&budget_path)?;
+        let report = check_budget(&tree.crate_totals(options.weight), &budget);
+        sink.text(&report_text(&report, human))?;
+        let crates: Vec<String> = report.over_budget().map(|e| e.name.clone()).collect();
+        if !crates.is_empty() {
+            return Err(BloatyError::OverBudget { crates });
+        }
+        return Ok(());
+    }
+
+    // Per crate sizes as multiples of a reference crate
+    if let Some(reference) = relative_to_crate {
+        let report =
+            bloaty_metafile::relative_to_crate(&tree.crate_totals(options.weight), &reference)?;
+        return sink.text(&report_text(&report, human));
+    }
 
-    // Serialize to JSON
-    let s = serde_json::to_string(&meta)?;
+    if let Some(dir) = split_by_crate {
+        return write_split_by_crate(&tree, &options, &dir, style);
+    }
+
+    // The tree formats don't build a metafile, build one to compare against the baseline
+    let tree_format = !matches!(
+        format,
+        Format::Metafile | Format::Summary | Format::Ndjson | Format::Versions
+    );
+    if let Some(base_path) = &relative_to
+        && tree_format
+    {
+        let meta = match &root_at {
+            Some(root) => tree.to_metafile_rooted_at(root, &name, &options)?,
+            None => tree.to_metafile(&name, &options),
+        };
+        if print_relative_to(base_path, &meta, diff_json, human, &sink)? {
+            return Ok(());
+        }
+    }
+
+    match format {
+        Format::Nested => {
+            let json = timed(&mut phases.convert, || tree.to_nested_json());
+            let s = timed(&mut phases.serialize, || serde_json::to_string(&json))?;
+            return sink.text(&s);
+        }
+        Format::Html => {
+            let json = timed(&mut phases.convert, || tree.to_nested_json());
+            let s = timed(&mut phases.serialize, || to_html(&json));
+            return sink.text(&s);
+        }
+        Format::Webtreemap => {
+            let json = timed(&mut phases.convert, || {
+                tree.to_webtreemap_json(options.weight)
+            });
+            let s = timed(&mut phases.serialize, || serde_json::to_string(&json))?;
+            return sink.text(&s);
+        }
+        #[cfg(feature = "pprof")]
+        Format::Pprof => {
+            let profile = timed(&mut phases.convert, || tree.to_pprof());
+            return timed(&mut phases.serialize, || {
+                sink.stream(|writer| write_pprof(&profile, writer))
+            });
+        }
+        Format::Metafile | Format::Summary | Format::Ndjson | Format::Versions => {}
+    }
+
+    let start = Instant::now();
+    let max_bytes = max_bytes.or(fit_analyzer.then_some(MAX_JSON_LENGTH));
+    let (meta, json) = match (root_at, max_bytes) {
+        (Some(root), _) => (tree.to_metafile_rooted_at(&root, &name, &options)?, None),
+        (None, Some(max_bytes)) => {
+            let (meta, s, deep) = to_json_string_within(&tree, &name, &options, max_bytes)?;
+            if deep != options.deep {
+                eprintln!("Using --deep={deep} to fit the JSON output in {max_bytes} bytes");
+            }
+            if s.len() > max_bytes {
+                eprintln!(
+                    "{}",
+                    style.warning(format_args!(
+                        "JSON output ({} bytes) doesn't fit in {max_bytes} bytes even with --deep=1",
+                        s.len()
+                    ))
+                );
+            }
+            (meta, Some(s))
+        }
+        (None, None) => (tree.to_metafile(&name, &options), None),
+    };
+    phases.convert = start.elapsed();
 
-    // Check if JSON string is too large (JavaScript string length limit)
-    // JavaScript max string length is 2^30 - 1 (0x3fffffff) characters
-    // But V8 uses 0x1fffffe8 as practical limit
-    const MAX_JSON_LENGTH: usize = 0x1fff_ffe8; // ~536MB
+    // Compare against the baseline metafile
+    if let Some(base_path) = &relative_to
+        && print_relative_to(base_path, &meta, diff_json, human, &sink)?
+    {
+        return Ok(());
+    }
+
+    if format == Format::Summary {
+        let summary = summarize_inputs(&meta);
+        let s = timed(&mut phases.serialize, || serde_json::to_string(&summary))?;
+        return sink.text(&s);
+    }
+    if format == Format::Ndjson {
+        return timed(&mut phases.serialize, || {
+            sink.stream(|writer| write_ndjson(&meta, writer))
+        });
+    }
+
+    // Serialize to JSON with a stable key order, unless done already to fit --max-bytes
+    let s = match json {
+        Some(s) => s,
+        None => timed(&mut phases.serialize, || to_json_string(&meta))?,
+    };
 
     let json_len = s.len();
 
     if json_len > MAX_JSON_LENGTH {
         eprintln!(
-            "Warning: JSON output is too large ({} bytes, {} MB)",
-            json_len,
-            json_len >> 20
+            "{}",
+            style.warning(format_args!(
+                "JSON output is too large ({} bytes, {} MB)",
+                json_len,
+                json_len >> 20
+            ))
         );
         eprintln!("This exceeds JavaScript's maximum string length (0x1fffffe8 characters)");
         eprintln!("The output may not be usable in web-based tools like esbuild analyzer");
     }
 
-    println!("{s}");
+    sink.text(&s)
+}
+
+/// A text report, with sizes like `1.2 MB` if `human` (the alternate flag of the reports)
+fn report_text(report: &impl std::fmt::Display, human: bool) -> String {
+    if human {
+        format!("{report:#}")
+    } else {
+        report.to_string()
+    }
+}
+
+/// Where the output goes: stdout, the first client of `--listen` or the `--pipe` file
+enum Sink {
+    Stdout,
+    Listen(String),
+    Pipe(String),
+}
+
+impl Sink {
+    /// Write text output, followed by a newline on stdout
+    fn text(&self, s: &str) -> Result<(), BloatyError> {
+        match self {
+            Sink::Stdout => {
+                println!("{s}");
+                Ok(())
+            }
+            _ => self.bytes(s.as_bytes()),
+        }
+    }
+
+    /// Write output as is
+    fn bytes(&self, bytes: &[u8]) -> Result<(), BloatyError> {
+        match self {
+            Sink::Stdout => {
+                let mut stdout = std::io::stdout().lock();
+                stdout
+                    .write_all(bytes)
+                    .and_then(|()| stdout.flush())
+                    .map_err(|source| BloatyError::FileWrite {
+                        path: "stdout".to_string(),
+                        source,
+                    })
+            }
+            Sink::Listen(addr) => serve_once(addr, bytes),
+            Sink::Pipe(path) => write_pipe(path, bytes),
+        }
+    }
 
-    Ok(())
+    /// Write output produced by `write`, streamed to stdout and buffered for a connection or
+    /// a pipe
+    fn stream(
+        &self,
+        write: impl FnOnce(&mut dyn Write) -> Result<(), BloatyError>,
+    ) -> Result<(), BloatyError> {
+        match self {
+            Sink::Stdout => write(&mut std::io::BufWriter::new(std::io::stdout().lock())),
+            _ => {
+                let mut buffer = Vec::new();
+                write(&mut buffer)?;
+                self.bytes(&buffer)
+            }
+        }
+    }
+}
+
+/// Phase timings of `--timings`, printed to stderr when dropped so every exit path of `run`
+/// reports them
+struct TimingsReport {
+    phases: Timings,
+    enabled: bool,
+}
+
+impl std::ops::Deref for TimingsReport {
+    type Target = Timings;
+
+    fn deref(&self) -> &Timings {
+        &self.phases
+    }
+}
+
+impl std::ops::DerefMut for TimingsReport {
+    fn deref_mut(&mut self) -> &mut Timings {
+        &mut self.phases
+    }
+}
+
+impl Drop for TimingsReport {
+    fn drop(&mut self) {
+        if self.enabled {
+            eprint!("{}", self.phases);
+        }
+    }
+}
+
+/// Run `f` and add its duration to `phase`
+fn timed<T>(phase: &mut Duration, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let value = f();
+    *phase += start.elapsed();
+    value
+}
+
+/// Accept one connection on `addr` and write `bytes` to it, for live viewers
+fn serve_once(addr: &str, bytes: &[u8]) -> Result<(), BloatyError> {
+    let connection = |source| BloatyError::Connection {
+        addr: addr.to_string(),
+        source,
+    };
+    let listener = std::net::TcpListener::bind(addr).map_err(connection)?;
+    eprintln!("Waiting for a connection on {addr}");
+    let (mut stream, _) = listener.accept().map_err(connection)?;
+    stream.write_all(bytes).map_err(connection)?;
+    stream.flush().map_err(connection)
+}
+
+/// Write `bytes` to a named pipe or file, opening a pipe blocks until a reader opens it
+fn write_pipe(path: &str, bytes: &[u8]) -> Result<(), BloatyError> {
+    let write = |source| BloatyError::FileWrite {
+        path: path.to_string(),
+        source,
+    };
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .map_err(write)?;
+    file.write_all(bytes).map_err(write)
+}
+
+/// Run `bloaty -d <dimensions> -n 0 --csv <bin>` and return its CSV output
+fn run_bloaty(bin: &str, dimensions: &str) -> Result<String, BloatyError> {
+    let output = std::process::Command::new("bloaty")
+        .args(["-d", dimensions, "-n", "0", "--csv", bin])
+        .output()
+        .map_err(|source| match source.kind() {
+            std::io::ErrorKind::NotFound => BloatyError::BloatyNotFound,
+            _ => BloatyError::BloatyFailed {
+                message: source.to_string(),
+            },
+        })?;
+    if !output.status.success() {
+        return Err(BloatyError::BloatyFailed {
+            message: format!(
+                "{}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        });
+    }
+    String::from_utf8(output.stdout).map_err(|e| BloatyError::BloatyFailed {
+        message: format!("output is not UTF-8: {e}"),
+    })
+}
+
+/// Read CSV input from a file or stdin
+/// Gzip input (a `.gz` path or gzip magic bytes) is transparently decompressed
+fn read_input(path: Option<&str>) -> Result<String, BloatyError> {
+    let (name, bytes) = match path {
+        Some(file_path) => (
+            file_path.to_string(),
+            std::fs::read(file_path).map_err(|source| BloatyError::FileRead {
+                path: file_path.to_string(),
+                source,
+            })?,
+        ),
+        None => {
+            let mut bytes = Vec::new();
+            std::io::stdin()
+                .read_to_end(&mut bytes)
+                .map_err(|source| BloatyError::FileRead {
+                    path: "stdin".to_string(),
+                    source,
+                })?;
+            ("stdin".to_string(), bytes)
+        }
+    };
+
+    if name.ends_with(".gz") || bytes.starts_with(&GZIP_MAGIC) {
+        let mut csv = String::new();
+        MultiGzDecoder::new(bytes.as_slice())
+            .read_to_string(&mut csv)
+            .map_err(|source| BloatyError::Decompression { path: name, source })?;
+        return Ok(csv);
+    }
+
+    String::from_utf8(bytes).map_err(|e| BloatyError::FileRead {
+        path: name,
+        source: std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+    })
+}
+
+/// Write a pprof profile gzip compressed to `writer`
+#[cfg(feature = "pprof")]
+fn write_pprof(profile: &[u8], writer: &mut dyn Write) -> Result<(), BloatyError> {
+    use flate2::{Compression, write::GzEncoder};
+
+    let write_error = |source| BloatyError::FileWrite {
+        path: "stdout".to_string(),
+        source,
+    };
+    let mut encoder = GzEncoder::new(writer, Compression::default());
+    encoder.write_all(profile).map_err(write_error)?;
+    encoder
+        .finish()
+        .map_err(write_error)?
+        .flush()
+        .map_err(write_error)
+}
+
+/// Read and parse a JSON configuration file
+fn read_json<T: serde::de::DeserializeOwned>(path: &str) -> Result<T, BloatyError> {
+    let s = std::fs::read_to_string(path).map_err(|source| BloatyError::FileRead {
+        path: path.to_string(),
+        source,
+    })?;
+    serde_json::from_str(&s).map_err(|source| BloatyError::JsonParse {
+        path: path.to_string(),
+        source,
+    })
+}
+
+/// Parse a `key=value` argument
+fn parse_key_value<V>(s: &str) -> Result<(String, V), String>
+where
+    V: std::str::FromStr,
+    V::Err: std::fmt::Display,
+{
+    let (k, v) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected key=value, got `{s}`"))?;
+    let v = v
+        .trim()
+        .parse()
+        .map_err(|e| format!("invalid value in `{s}`: {e}"))?;
+    Ok((k.trim().to_string(), v))
+}
+
+/// Write one metafile per top-level crate into `dir`, plus an `index.json`
+/// listing each crate with its file name and total bytes
+fn write_split_by_crate(
+    tree: &Tree,
+    options: &ConvertOptions,
+    dir: &str,
+    style: Style,
+) -> Result<(), BloatyError> {
+    let dir = std::path::Path::new(dir);
+    std::fs::create_dir_all(dir).map_err(|source| BloatyError::FileWrite {
+        path: dir.display().to_string(),
+        source,
+    })?;
+
+    let mut index = Vec::new();
+    // `index` is taken by the index itself, names are compared case-insensitively for
+    // case-insensitive file systems
+    let mut used = HashSet::from(["index".to_string()]);
+    for (name, meta) in tree.to_crate_metafiles(options) {
+        let file = format!("{}.json", file_stem(&name, &mut used));
+        let path = dir.join(&file);
+        let s = to_json_string(&meta)?;
+        if s.len() > MAX_JSON_LENGTH {
+            eprintln!(
+                "{}",
+                style.warning(format_args!(
+                    "{} is too large ({} MB)",
+                    path.display(),
+                    s.len() >> 20
+                ))
+            );
+        }
+        std::fs::write(&path, s).map_err(|source| BloatyError::FileWrite {
+            path: path.display().to_string(),
+            source,
+        })?;
+
+        let bytes: u64 = meta.outputs.values().map(|output| output.bytes).sum();
+        index.push(serde_json::json!({ "name": name, "file": file, "bytes": bytes }));
+    }
+
+    let path = dir.join("index.json");
+    std::fs::write(&path, serde_json::to_string(&index)?).map_err(|source| BloatyError::FileWrite {
+        path: path.display().to_string(),
+        source,
+    })
+}
+
+/// Print the diff of `meta` against the baseline metafile at `base_path` to stderr
+/// Returns true if it was written as JSON to `sink` instead (`--diff-json`), replacing the output
+fn print_relative_to(
+    base_path: &str,
+    meta: &Metafile,
+    diff_json: bool,
+    human: bool,
+    sink: &Sink,
+) -> Result<bool, BloatyError> {
+    let base = std::fs::read_to_string(base_path).map_err(|source| BloatyError::FileRead {
+        path: base_path.to_string(),
+        source,
+    })?;
+    let base: Metafile =
+        serde_json::from_str(&base).map_err(|source| BloatyError::MetafileParse {
+            path: base_path.to_string(),
+            source,
+        })?;
+    let report = diff_metafiles(&base, meta);
+    if diff_json {
+        sink.text(&serde_json::to_string(&report)?)?;
+        return Ok(true);
+    }
+    eprintln!("{}", report_text(&report, human));
+    Ok(false)
+}
+
+/// A file name without extension for a node name like `COMPONENT:x` or `rand v0.8`
+/// Characters outside `[A-Za-z0-9._-]` become `_`, a stem already in `used` gets a `_2`, `_3`,
+/// ... suffix
+fn file_stem(name: &str, used: &mut HashSet<String>) -> String {
+    let stem: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let stem = if stem.is_empty() || stem.chars().all(|c| c == '.') {
+        format!("_{stem}")
+    } else {
+        stem
+    };
+    let mut candidate = stem.clone();
+    let mut n = 2;
+    while !used.insert(candidate.to_lowercase()) {
+        candidate = format!("{stem}_{n}");
+        n += 1;
+    }
+    candidate
+}
+
+#[cfg(test)]
+mod test {
+    use super::file_stem;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_file_stem() {
+        let mut used = HashSet::from(["index".to_string()]);
+        let stems: Vec<_> = [
+            "serde",
+            "COMPONENT:net",
+            "[proc-macro]",
+            "rand v0.8",
+            "crate:foo",
+            "crate_foo",
+            "Serde",
+            "index",
+            "..",
+        ]
+        .iter()
+        .map(|name| file_stem(name, &mut used))
+        .collect();
+        assert_eq!(
+            stems,
+            [
+                "serde",
+                "COMPONENT_net",
+                "_proc-macro_",
+                "rand_v0.8",
+                "crate_foo",
+                "crate_foo_2",
+                "Serde_2",
+                "index_2",
+                "_..",
+            ]
+        );
+    }
 }
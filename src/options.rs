@@ -0,0 +1,141 @@
+use crate::rewrite::SymbolRegex;
+use std::collections::HashMap;
+
+/// What the `bytes` of the metafile inputs measure
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Weight {
+    /// File size in bytes
+    #[default]
+    Bytes,
+    /// Virtual memory size in bytes
+    Vmsize,
+    /// Number of CSV records (symbols)
+    Count,
+}
+
+/// Which dependency path a crate is placed under when it is reachable through several
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathStrategy {
+    /// The path with the fewest crates (breadth-first)
+    #[default]
+    Shortest,
+    /// The first path found walking dependencies breadth-first, without preferring the
+    /// shortest of several packages with the same crate name
+    First,
+    /// The path with the most crates, ignoring dependency cycles
+    Longest,
+}
+
+/// Options controlling the conversion from bloaty CSV to metafile
+#[derive(Debug, Clone, Default)]
+pub struct ConvertOptions {
+    /// Path to Cargo.lock file for dependency resolution (defaults to "Cargo.lock")
+    pub lock: Option<String>,
+    /// Don't load a lockfile, every crate is a top-level node
+    pub no_lock: bool,
+    /// Maximum depth for tree traversal (0 means unlimited)
+    pub deep: usize,
+    /// Per top-level node (crate) depth limits, overriding `deep`
+    pub deep_overrides: HashMap<String, usize>,
+    /// Keep the nodes below this depth with zero size, the node at the depth carries its subtree
+    /// total (0 means disabled)
+    pub collapse_after: usize,
+    /// What the metafile `bytes` measure, file size or number of symbols
+    pub weight: Weight,
+    /// What the output's `bytesInOutput` (and the output `bytes`) measure, defaults to `weight`
+    pub output_weight: Option<Weight>,
+    /// Keep only the largest children of each node, folding the rest into `[others]` (0 means unlimited)
+    pub max_children: usize,
+    /// Keep at most this many inputs, largest first, folding the rest into `[overflow]` (0 means unlimited)
+    pub max_inputs: usize,
+    /// Merge sibling leaves that only differ in their generic arguments into one `name<_>` leaf
+    pub merge_generic_siblings: bool,
+    /// Remove nodes whose total size (measured by `weight`) is zero
+    pub prune_empty: bool,
+    /// Exclude section-level entries from the output
+    pub no_sections: bool,
+    /// Only keep records in these sections, e.g. `.text` (empty keeps every section)
+    pub only_sections: Vec<String>,
+    /// Stop paths at the section, so each section node carries the sum of its symbols
+    pub sections_as_leaves: bool,
+    /// Leave out the section level for symbols attributed to a crate
+    pub flatten_sections: bool,
+    /// Append the section to the leaf symbol name (`symbol [.text]`) instead of adding a level
+    pub section_suffix: bool,
+    /// Group entry point and startup symbols (`main`, `_start`, `lang_start`) under `ENTRY`
+    pub entry_bucket: bool,
+    /// Attribute slice methods like `<[alloc::string::String]>::concat` to the element type's crate
+    /// instead of `std::primitive::slice`
+    pub slice_element_crate: bool,
+    /// Map platform specific section names (`.text`, `__text`, `.text$mn`) to canonical ones (`text`)
+    pub section_canonical: bool,
+    /// Merge PE/COFF grouped sections into their section, e.g. `.text$mn` and `.text$x` into `.text`
+    pub merge_pe_sections: bool,
+    /// Leave out the section level of records with an empty section, instead of `[no-section]`
+    pub drop_empty_sections: bool,
+    /// Reported `(vmsize, filesize)` of each section, see [`crate::parse_section_sizes`]
+    /// The gap to the sum of a section's symbols is added as `SECTIONS/<section>/[padding]`
+    pub section_sizes: HashMap<String, (u64, u64)>,
+    /// Tag each leaf with the kind of its section (`code`, `rodata`, `data`, `bss`, `unwind`),
+    /// see `Node::kind`
+    pub symbol_kinds: bool,
+    /// List the top-level inputs in `Output.imports`
+    pub emit_imports: bool,
+    /// Leave the top-level `inputs` of the metafile empty, the sizes are only in `outputs`
+    pub outputs_only: bool,
+    /// Accept CSV input without any data records instead of returning `BloatyError::EmptyInput`
+    pub allow_empty: bool,
+    /// Collect the symbols that could not be attributed to a crate, see `Tree::unattributed`
+    pub collect_unattributed: bool,
+    /// Collect the crate and path of every symbol, see `Tree::resolution`
+    pub collect_resolution: bool,
+    /// The CSV has no header line, columns are read as `sections,symbols,vmsize,filesize`
+    pub no_header: bool,
+    /// Fail with `BloatyError::LockfileMismatch` instead of only reporting it in
+    /// `Tree::lockfile_mismatch` if the lockfile doesn't match the CSV
+    pub strict: bool,
+    /// How to choose between several dependency paths to the same crate
+    pub path_strategy: PathStrategy,
+    /// Label crates that have several semver incompatible versions in the lockfile
+    /// with their major version, e.g. `rand v0.8`
+    pub group_by_version_major: bool,
+    /// Add a top level for where crates come from, `REGISTRY`, `GIT` or `LOCAL`
+    pub group_by_source: bool,
+    /// Place every crate below the direct dependency of the root that pulls it in
+    /// (`app/foo/baz/bar` -> `foo/bar`)
+    pub group_by_direct_dep: bool,
+    /// Crate name aliases (`from` -> `to`), applied after `-` is normalized to `_`
+    pub aliases: HashMap<String, String>,
+    /// Module path prefixes (`tokio::runtime`) whose symbols are grouped under `RUNTIME`
+    pub runtime_prefixes: Vec<String>,
+    /// Proc-macro crates, their symbols are tagged with a `[proc-macro]` level above the crate
+    pub proc_macros: Vec<String>,
+    /// Leave out the symbols of `proc_macros` instead of tagging them
+    pub exclude_proc_macros: bool,
+    /// Attribute closures inside another crate's generic code to the crate found in the generic arguments
+    pub blame_closures: bool,
+    /// Attribute `core::iter`/`core::ops` symbols (iterator adapters, closure calls) to the
+    /// crate found in their generic arguments
+    pub collapse_std_generics: bool,
+    /// Strip `#N` disambiguators from symbol parts (`bar#2` -> `bar`), keeping `{closure#0}`
+    pub strip_disambiguators: bool,
+    /// Module path prefixes (`tokio::net`) to feature names, adding a `FEATURE:<name>` level
+    pub feature_map: HashMap<String, String>,
+    /// C symbol patterns (`openssl_*`, or an exact name) to the crate their code belongs to,
+    /// e.g. the `-sys` crate whose build script compiled the C library
+    pub c_symbols: HashMap<String, String>,
+    /// Crate name prefixes (`hyper`) to component names, adding a `COMPONENT:<name>` top level
+    pub components: HashMap<String, String>,
+    /// Rules applied in order to every symbol before it is split into a path
+    pub symbol_regex: Vec<SymbolRegex>,
+    /// Collect `(section, symbol, path)` of the records whose symbol contains this substring
+    /// (an empty string traces every record), see `Tree::traces`
+    pub trace_symbol: Option<String>,
+}
+
+impl ConvertOptions {
+    /// The weight used for the output's `bytesInOutput`, `output_weight` or else `weight`
+    pub fn output_weight(&self) -> Weight {
+        self.output_weight.unwrap_or(self.weight)
+    }
+}
@@ -1,45 +1,228 @@
-use crate::{tool::get_crate_name, tree::SectionRecord};
-use cargo_lock::dependency::{
-    Tree,
-    graph::{Graph, NodeIndex},
+use crate::{
+    options::{ConvertOptions, PathStrategy},
+    report::{CrateVersion, VersionsReport},
+    tool::{GIT_NAME, LOCAL_NAME, REGISTRY_NAME, STD_CRATES, crate_node_name, get_crate_name_with},
+    tree::SectionRecord,
+};
+use cargo_lock::{
+    Lockfile, Package, Version,
+    dependency::{
+        Tree,
+        graph::{Graph, NodeIndex},
+    },
+};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet, VecDeque},
+    rc::Rc,
 };
-use std::collections::{HashMap, HashSet, VecDeque};
 
 /// Package dependency resolver
 /// Maps crate names to their dependency paths in the dependency tree
 #[derive(Debug, Default, Clone)]
 pub struct Packages {
     parent: HashMap<String, Vec<String>>,
+    /// With `group_by_version_major`, the path of each major version of the split crates
+    majors: HashMap<String, HashMap<String, Vec<String>>>,
+    aliases: HashMap<String, String>,
+    /// Number of non-std crates of the records found in the dependency tree, and their total
+    overlap: Option<(usize, usize)>,
 }
 
+/// Below this percentage of the records' crates found in the lockfile, the lockfile is
+/// probably for another project, see `Packages::lockfile_mismatch`
+const MIN_LOCKFILE_OVERLAP_PERCENT: usize = 10;
+
+/// How many times deeper than the median a crate must be to be reported by `depth_outliers`
+const DEPTH_OUTLIER_FACTOR: usize = 3;
+
+/// How many levels deeper than the median a crate must be to be reported by `depth_outliers`
+const DEPTH_OUTLIER_MIN_EXTRA: usize = 4;
+
 /// Helper function to normalize crate names by replacing hyphens with underscores
 #[inline]
 fn normalize_crate_name(name: &str) -> String {
     name.replace('-', "_")
 }
 
-/// Node used in breadth-first search traversal of the dependency graph
+/// Apply a crate name alias, returning the original name if there is none
+#[inline]
+fn resolve_alias<'a>(aliases: &'a HashMap<String, String>, name: &'a str) -> &'a str {
+    aliases.get(name).map(String::as_str).unwrap_or(name)
+}
+
+/// Normalized crate name aliases from the options
+fn normalize_aliases(options: &ConvertOptions) -> HashMap<String, String> {
+    options
+        .aliases
+        .iter()
+        .map(|(from, to)| (normalize_crate_name(from), normalize_crate_name(to)))
+        .collect()
+}
+
+/// Node with its path from a root, used when walking the dependency graph
 struct BfsNode {
     name: Box<str>,
-    path: Vec<String>,
+    path: Rc<PathLink>,
     index: NodeIndex,
 }
 
+/// Path from a root as a linked list from the last crate back to the root,
+/// so the paths of all neighbors share the path of their parent instead of copying it
+struct PathLink {
+    label: String,
+    parent: Option<Rc<PathLink>>,
+    len: usize,
+}
+
+impl PathLink {
+    /// Number of crates in the path
+    #[inline]
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Materialize the path, from the root to the last crate
+    fn to_vec(&self) -> Vec<String> {
+        let mut path = Vec::with_capacity(self.len);
+        let mut link = Some(self);
+        while let Some(PathLink { label, parent, .. }) = link {
+            path.push(label.clone());
+            link = parent.as_deref();
+        }
+        path.reverse();
+        path
+    }
+}
+
+/// Names of the packages in the dependency graph
+struct CrateNames<'a> {
+    aliases: &'a HashMap<String, String>,
+    /// Crates with several semver incompatible versions, labeled with their major version
+    split_majors: HashSet<String>,
+}
+
+impl<'a> CrateNames<'a> {
+    /// With `group_by_version_major`, find the crates with several incompatible versions
+    fn new(g: &Graph, aliases: &'a HashMap<String, String>, group_by_version_major: bool) -> Self {
+        let mut names = Self {
+            aliases,
+            split_majors: HashSet::new(),
+        };
+        if group_by_version_major {
+            let mut majors: HashMap<String, HashSet<String>> = HashMap::new();
+            for index in g.node_indices() {
+                majors
+                    .entry(names.name(g, index))
+                    .or_default()
+                    .insert(major_version(&g[index].version));
+            }
+            names.split_majors = majors
+                .into_iter()
+                .filter(|(_, majors)| majors.len() > 1)
+                .map(|(name, _)| name)
+                .collect();
+        }
+        names
+    }
+
+    /// Normalized and aliased crate name of a package
+    fn name(&self, g: &Graph, index: NodeIndex) -> String {
+        let name = normalize_crate_name(g[index].name.as_str());
+        resolve_alias(self.aliases, &name).to_string()
+    }
+
+    /// Name of a package in dependency paths, `rand v0.8` if `rand` has several major versions
+    /// Crates named like a bucket are prefixed, see [`crate_node_name`]
+    fn label(&self, g: &Graph, index: NodeIndex, name: &str) -> String {
+        let label = crate_node_name(name);
+        if self.split_majors.contains(name) {
+            format!("{label} {}", major_version(&g[index].version))
+        } else {
+            label.into_owned()
+        }
+    }
+}
+
+/// Semver compatibility group of a version, `v1` for 1.2.3, `v0.8` for 0.8.5, `v0.0.3` for 0.0.3
+fn major_version(version: &Version) -> String {
+    match (version.major, version.minor) {
+        (0, 0) => format!("v0.0.{}", version.patch),
+        (0, minor) => format!("v0.{minor}"),
+        (major, _) => format!("v{major}"),
+    }
+}
+
+/// Version of the package `name` in a path like a compile unit from the registry:
+/// `~/.cargo/registry/src/index.crates.io-6f17d22bba15001f/rand-0.9.0/src/lib.rs` -> 0.9.0
+fn version_in_path(path: &str, name: &str) -> Option<Version> {
+    path.split(['/', '\\']).find_map(|component| {
+        let version = component.get(name.len()..)?.strip_prefix('-')?;
+        if normalize_crate_name(&component[..name.len()]) != name {
+            return None;
+        }
+        version.parse().ok()
+    })
+}
+
+/// Top-level bucket of a package by where it comes from: crates.io or another registry,
+/// a git repository, or a local path (workspace members have no source in the lockfile)
+fn source_bucket(package: &Package) -> &'static str {
+    match &package.source {
+        Some(source) if source.is_git() => GIT_NAME,
+        Some(source) if source.is_path() => LOCAL_NAME,
+        Some(_) => REGISTRY_NAME,
+        None => LOCAL_NAME,
+    }
+}
+
+/// List every package in the lockfile with its version and source, flagging crates with
+/// several semver incompatible versions (`rand 0.7.3` and `rand 0.8.5`)
+pub fn versions_report(lockfile: &Lockfile) -> VersionsReport {
+    let mut majors: HashMap<&str, HashSet<String>> = HashMap::new();
+    for package in &lockfile.packages {
+        majors
+            .entry(package.name.as_str())
+            .or_default()
+            .insert(major_version(&package.version));
+    }
+    let mut packages: Vec<&Package> = lockfile.packages.iter().collect();
+    // By semver before the versions become strings, 0.7.3 before 0.10.0
+    packages.sort_by(|a, b| {
+        (&a.name, &a.version, source_bucket(a)).cmp(&(&b.name, &b.version, source_bucket(b)))
+    });
+    let crates = packages
+        .into_iter()
+        .map(|package| CrateVersion {
+            name: package.name.to_string(),
+            version: package.version.to_string(),
+            source: source_bucket(package).to_string(),
+            duplicate: majors[package.name.as_str()].len() > 1,
+        })
+        .collect();
+    VersionsReport { crates }
+}
+
 impl BfsNode {
     /// Create a BFS node from a graph index with an optional parent path
-    /// If parent_path is None, creates a root node; otherwise extends the path
+    /// If parent_path is None, creates a root node; otherwise extends the (shared) path
     #[inline]
-    fn from_graph(g: &Graph, index: NodeIndex, parent_path: Option<Vec<String>>) -> Self {
-        let name = normalize_crate_name(g[index].name.as_str());
+    fn from_graph(
+        g: &Graph,
+        index: NodeIndex,
+        parent_path: Option<Rc<PathLink>>,
+        names: &CrateNames,
+    ) -> Self {
+        let name = names.name(g, index);
+        let label = names.label(g, index, &name);
         let name_boxed: Box<str> = name.as_str().into();
 
-        let path = match parent_path {
-            Some(mut p) => {
-                p.push(name);
-                p
-            }
-            None => vec![name],
-        };
+        let len = parent_path.as_ref().map_or(0, |p| p.len()) + 1;
+        let path = Rc::new(PathLink {
+            label,
+            parent: parent_path,
+            len,
+        });
 
         Self {
             name: name_boxed,
@@ -49,73 +232,714 @@ impl BfsNode {
     }
 }
 
+/// Dependency neighbors of a node, sorted by package name and version
+fn sorted_neighbors(g: &Graph, index: NodeIndex) -> Vec<NodeIndex> {
+    let mut neighbors: Vec<_> = g.neighbors(index).collect();
+    neighbors.sort_by(|a, b| (&g[*a].name, &g[*a].version).cmp(&(&g[*b].name, &g[*b].version)));
+    neighbors.dedup();
+    neighbors
+}
+
+/// Longest path to each node from the roots
+/// Edges closing a dependency cycle are ignored, so the remaining graph is acyclic
+fn longest_paths(g: &Graph, roots: &[NodeIndex], names: &CrateNames) -> Vec<BfsNode> {
+    // Depth-first postorder, reversed it is a topological order of the acyclic part
+    let mut visited = HashSet::with_capacity(g.node_count());
+    let mut postorder = Vec::with_capacity(g.node_count());
+    for &root in roots {
+        if !visited.insert(root) {
+            continue;
+        }
+        let mut stack = vec![(root, sorted_neighbors(g, root), 0)];
+        while let Some((index, neighbors, next)) = stack.last_mut() {
+            if let Some(&neighbor) = neighbors.get(*next) {
+                *next += 1;
+                if visited.insert(neighbor) {
+                    stack.push((neighbor, sorted_neighbors(g, neighbor), 0));
+                }
+            } else {
+                postorder.push(*index);
+                stack.pop();
+            }
+        }
+    }
+    postorder.reverse();
+    let order: HashMap<NodeIndex, usize> = postorder
+        .iter()
+        .enumerate()
+        .map(|(i, &index)| (index, i))
+        .collect();
+
+    let mut nodes: HashMap<NodeIndex, BfsNode> = roots
+        .iter()
+        .map(|&root| (root, BfsNode::from_graph(g, root, None, names)))
+        .collect();
+    for &index in &postorder {
+        let Some(path) = nodes.get(&index).map(|node| node.path.clone()) else {
+            continue;
+        };
+        for neighbor in sorted_neighbors(g, index) {
+            // Edges going back in the topological order close a cycle
+            if order[&neighbor] <= order[&index] {
+                continue;
+            }
+            let longer = nodes
+                .get(&neighbor)
+                .is_none_or(|node| node.path.len() < path.len() + 1);
+            if longer {
+                nodes.insert(
+                    neighbor,
+                    BfsNode::from_graph(g, neighbor, Some(path.clone()), names),
+                );
+            }
+        }
+    }
+
+    postorder
+        .iter()
+        .filter_map(|index| nodes.remove(index))
+        .collect()
+}
+
+/// Shortest path to each node from the roots, in breadth-first order
+fn breadth_first_paths(g: &Graph, roots: &[NodeIndex], names: &CrateNames) -> Vec<BfsNode> {
+    // Pre-allocate collections with estimated capacity
+    let estimated_nodes = g.node_count();
+    let mut visited = HashSet::with_capacity(estimated_nodes);
+    let mut queue = VecDeque::with_capacity(estimated_nodes / 4);
+    let mut nodes = Vec::with_capacity(estimated_nodes);
+
+    // Initialize queue with root nodes
+    for &start in roots {
+        queue.push_back(BfsNode::from_graph(g, start, None, names));
+    }
+
+    while let Some(node) = queue.pop_front() {
+        if !visited.insert(node.index) {
+            continue;
+        }
+
+        // Add unvisited neighbors to queue
+        for neighbor in g.neighbors(node.index) {
+            if !visited.contains(&neighbor) {
+                queue.push_back(BfsNode::from_graph(
+                    g,
+                    neighbor,
+                    Some(node.path.clone()),
+                    names,
+                ));
+            }
+        }
+        nodes.push(node);
+    }
+    nodes
+}
+
 impl Packages {
     /// Create a new Packages resolver from a dependency tree and section records
-    /// Uses BFS to find the shortest path to each crate in the dependency graph
-    pub fn new(tree: &Tree, records: &[SectionRecord]) -> Self {
+    /// `options.path_strategy` selects the path to each crate in the dependency graph
+    /// (shortest by default), and decides between packages that share a crate name
+    /// Crate name aliases from `options` are applied to both graph and symbol crate names
+    pub fn new(tree: &Tree, records: &[SectionRecord], options: &ConvertOptions) -> Self {
+        let aliases = normalize_aliases(options);
+
         // Build set of crate names from records
         let crates: HashSet<String> = records
             .iter()
-            .filter_map(|record| get_crate_name(&record.symbols))
-            .map(|(name, _)| name)
+            .filter_map(|record| get_crate_name_with(&record.symbols, options))
+            .map(|(name, _)| resolve_alias(&aliases, &name).to_string())
             .collect();
 
         let g = tree.graph();
-        let roots = tree.roots().to_vec();
+        let mut roots = tree.roots().to_vec();
+        // Without a root package (every package is a dependency, e.g. in a cycle) start from
+        // all packages, so each crate gets at least its own name as path
+        if roots.is_empty() {
+            roots = g.node_indices().collect();
+            roots.sort_by(|a, b| (&g[*a].name, &g[*a].version).cmp(&(&g[*b].name, &g[*b].version)));
+        }
+        let names = CrateNames::new(g, &aliases, options.group_by_version_major);
+
+        let nodes = match options.path_strategy {
+            // Breadth-first, `first` only differs in keeping the first package found among
+            // packages with the same crate name
+            PathStrategy::Shortest | PathStrategy::First => breadth_first_paths(g, &roots, &names),
+            PathStrategy::Longest => longest_paths(g, &roots, &names),
+        };
 
-        // Pre-allocate collections with estimated capacity
-        let estimated_nodes = g.node_count();
-        let mut visited = HashSet::with_capacity(estimated_nodes);
-        let mut queue = VecDeque::with_capacity(estimated_nodes / 4);
         let mut parent: HashMap<String, Vec<String>> = HashMap::with_capacity(crates.len());
+        let mut majors: HashMap<String, HashMap<String, Vec<String>>> = HashMap::new();
+        let mut exact_names: HashSet<String> = HashSet::with_capacity(crates.len());
 
-        // Initialize queue with root nodes
-        for &start in &roots {
-            queue.push_back(BfsNode::from_graph(g, start, None));
-        }
+        for BfsNode { name, path, index } in nodes {
+            let name_str = name.as_ref();
 
-        // BFS traversal to find shortest paths
-        while let Some(BfsNode { name, path, index }) = queue.pop_front() {
-            if visited.contains(&index) {
-                continue;
-            }
+            // The shared path is only materialized when it is stored
+            // REGISTRY/app/serde, the bucket of the crate itself, not of its dependents
+            let bucket = options
+                .group_by_source
+                .then(|| source_bucket(&g[index]).to_string());
+            let path_len = path.len() + usize::from(bucket.is_some());
+            let to_vec = || {
+                let mut path = path.to_vec();
+                // app/foo/baz/bar -> foo/bar, below the direct dependency of the root
+                if options.group_by_direct_dep && path.len() > 1 {
+                    path.drain(..1);
+                    if path.len() > 2 {
+                        path.drain(1..path.len() - 1);
+                    }
+                }
+                if let Some(bucket) = &bucket {
+                    path.insert(0, bucket.clone());
+                }
+                path
+            };
 
-            let name_str = name.as_ref();
+            // Distinct packages like `foo-bar` and `foo_bar` normalize to the same name,
+            // the package whose name matches the symbol crate name exactly is preferred
+            let exact = !g[index].name.as_str().contains('-');
+
+            // Every major version of a split crate keeps its own path, picked by strategy
+            if names.split_majors.contains(name_str) {
+                majors
+                    .entry(name_str.to_string())
+                    .or_default()
+                    .entry(major_version(&g[index].version))
+                    .and_modify(|entry| {
+                        let replace = match options.path_strategy {
+                            PathStrategy::Shortest => entry.len() > path_len,
+                            PathStrategy::First => false,
+                            PathStrategy::Longest => entry.len() < path_len,
+                        };
+                        if replace {
+                            *entry = to_vec();
+                        }
+                    })
+                    .or_insert_with(to_vec);
+            }
 
             // Insert or update path for this crate
             parent
                 .entry(name_str.to_string())
                 .and_modify(|entry| {
-                    // Keep shorter path if crate is in records
-                    if crates.contains(name_str) && entry.len() > path.len() {
-                        *entry = path.clone();
+                    let entry_exact = exact_names.contains(name_str);
+                    if exact && !entry_exact {
+                        *entry = to_vec();
+                        exact_names.insert(name_str.to_string());
+                    } else if exact == entry_exact && crates.contains(name_str) {
+                        // Several packages with this name, pick one by strategy if crate is in records
+                        let replace = match options.path_strategy {
+                            PathStrategy::Shortest => entry.len() > path_len,
+                            PathStrategy::First => false,
+                            PathStrategy::Longest => entry.len() < path_len,
+                        };
+                        if replace {
+                            *entry = to_vec();
+                        }
                     }
                 })
-                .or_insert_with(|| path.clone());
-
-            visited.insert(index);
-
-            // Add unvisited neighbors to queue
-            for neighbor in g.neighbors(index) {
-                if !visited.contains(&neighbor) {
-                    queue.push_back(BfsNode::from_graph(g, neighbor, Some(path.clone())));
-                }
-            }
+                .or_insert_with(|| {
+                    if exact {
+                        exact_names.insert(name_str.to_string());
+                    }
+                    to_vec()
+                });
         }
 
+        let user_crates: Vec<&String> = crates
+            .iter()
+            .filter(|name| !STD_CRATES.contains(&name.as_str()))
+            .collect();
+        let matched = user_crates
+            .iter()
+            .filter(|name| parent.contains_key(name.as_str()))
+            .count();
+        let overlap = Some((matched, user_crates.len()));
+
         // Ensure standard library crates (std, alloc) have entries
         for crate_name in crates {
             parent
                 .entry(crate_name.clone())
-                .or_insert_with(|| vec![crate_name]);
+                .or_insert_with(|| vec![crate_node_name(&crate_name).into_owned()]);
         }
 
-        Self { parent }
+        Self {
+            parent,
+            majors,
+            aliases,
+            overlap,
+        }
+    }
+
+    /// Create a resolver without a dependency tree, every crate is a top-level node
+    /// Crate name aliases from `options` are still applied
+    pub fn flat(options: &ConvertOptions) -> Self {
+        Self {
+            parent: HashMap::new(),
+            majors: HashMap::new(),
+            aliases: normalize_aliases(options),
+            overlap: None,
+        }
     }
 
-    /// Get the dependency path for a crate by ID
+    /// Get the dependency path for a crate by ID, after applying aliases
     /// Returns a reference to avoid cloning when possible
+    /// Empty for crates that are not in the dependency tree, see [`Packages::crate_name`]
     pub fn get_path(&self, id: &str) -> &[String] {
+        let id = resolve_alias(&self.aliases, id);
         self.parent.get(id).map(|v| v.as_slice()).unwrap_or(&[])
     }
+
+    /// Like [`Packages::get_path`], but a crate with several major versions is routed to the
+    /// major version found in the record's dimensions (`rand-0.9.0` in a compile unit path)
+    pub fn get_path_in(&self, id: &str, dimensions: &[String]) -> &[String] {
+        let id = resolve_alias(&self.aliases, id);
+        self.majors
+            .get(id)
+            .and_then(|majors| {
+                let version = dimensions.iter().find_map(|d| version_in_path(d, id))?;
+                majors.get(&major_version(&version))
+            })
+            .or_else(|| self.parent.get(id))
+            .map(|v| v.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Node name of a crate after applying aliases, the top-level node of crates without a
+    /// dependency path, see [`crate_node_name`]
+    pub fn crate_name<'a>(&'a self, id: &'a str) -> Cow<'a, str> {
+        crate_node_name(resolve_alias(&self.aliases, id))
+    }
+
+    /// `(found, total)` if few of the crates in the records are in the dependency tree,
+    /// which usually means the lockfile belongs to another project
+    /// Standard library crates are not counted, `None` without a dependency tree
+    pub fn lockfile_mismatch(&self) -> Option<(usize, usize)> {
+        self.overlap.filter(|&(matched, total)| {
+            total > 0 && matched * 100 < total * MIN_LOCKFILE_OVERLAP_PERCENT
+        })
+    }
+
+    /// Median dependency path length and the crates (sorted by name) whose path is far longer
+    /// At least `DEPTH_OUTLIER_FACTOR` times the median and `DEPTH_OUTLIER_MIN_EXTRA` levels
+    /// deeper, a hint that the dependency graph was resolved in a confusing way
+    pub fn depth_outliers(&self) -> (usize, Vec<(&str, usize)>) {
+        let mut depths: Vec<usize> = self.parent.values().map(Vec::len).collect();
+        if depths.is_empty() {
+            return (0, Vec::new());
+        }
+        depths.sort_unstable();
+        let median = depths[depths.len() / 2];
+        let mut outliers: Vec<(&str, usize)> = self
+            .parent
+            .iter()
+            .map(|(name, path)| (name.as_str(), path.len()))
+            .filter(|&(_, depth)| {
+                depth >= median * DEPTH_OUTLIER_FACTOR && depth >= median + DEPTH_OUTLIER_MIN_EXTRA
+            })
+            .collect();
+        outliers.sort_unstable();
+        (median, outliers)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::{Packages, versions_report};
+    use crate::{
+        options::{ConvertOptions, PathStrategy},
+        tool::resolve_symbol,
+        tree::SectionRecord,
+    };
+    use cargo_lock::Lockfile;
+    use std::str::FromStr;
+
+    /// Build a dependency tree from `(name, version, dependencies)` entries
+    pub(crate) fn dependency_tree(
+        packages: &[(&str, &str, &[&str])],
+    ) -> cargo_lock::dependency::Tree {
+        let mut toml = String::from("version = 3\n");
+        for (name, version, deps) in packages {
+            let deps: Vec<_> = deps.iter().map(|dep| format!("\"{dep}\"")).collect();
+            toml.push_str(&format!(
+                "\n[[package]]\nname = \"{name}\"\nversion = \"{version}\"\ndependencies = [{}]\n",
+                deps.join(", ")
+            ));
+        }
+        Lockfile::from_str(&toml)
+            .expect("Failed to parse lockfile")
+            .dependency_tree()
+            .expect("Failed to build dependency tree")
+    }
+
+    /// Build section records for the given symbols
+    pub(crate) fn records(symbols: &[&str]) -> Vec<SectionRecord> {
+        symbols
+            .iter()
+            .map(|symbol| SectionRecord {
+                sections: ".text".to_string(),
+                symbols: symbol.to_string(),
+                vmsize: 1,
+                filesize: 1,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_symbol() {
+        let tree = dependency_tree(&[("app", "0.1.0", &["foo"]), ("foo", "1.0.0", &[])]);
+        let packages = Packages::new(
+            &tree,
+            &records(&["foo::bar::baz", "app::main"]),
+            &ConvertOptions::default(),
+        );
+
+        assert_eq!(
+            resolve_symbol("foo::bar::baz", &packages),
+            Some(vec![
+                "app".to_string(),
+                "foo".to_string(),
+                "bar".to_string(),
+                "baz".to_string()
+            ])
+        );
+        assert_eq!(
+            resolve_symbol("<foo::Bar as core::fmt::Debug>::fmt", &packages),
+            Some(vec![
+                "app".to_string(),
+                "foo".to_string(),
+                "Bar".to_string(),
+                "fmt".to_string()
+            ])
+        );
+        assert_eq!(resolve_symbol("[100 Others]", &packages), None);
+    }
+
+    #[test]
+    fn test_normalized_name_collision() {
+        // `foo-bar` (via app) and `foo_bar` (via x) both normalize to `foo_bar`
+        let tree = dependency_tree(&[
+            ("app", "0.1.0", &["foo-bar", "x"]),
+            ("foo-bar", "1.0.0", &[]),
+            ("x", "1.0.0", &["foo_bar"]),
+            ("foo_bar", "1.0.0", &[]),
+        ]);
+        let packages = Packages::new(&tree, &records(&["foo_bar::f"]), &ConvertOptions::default());
+        // The exact name match wins even though `foo-bar` has a shorter path
+        assert_eq!(packages.get_path("foo_bar"), ["app", "x", "foo_bar"]);
+    }
+
+    #[test]
+    fn test_path_strategy() {
+        // Two diamonds: app -> {a, z}, c via a/x or z, d via a or z/w/v
+        let tree = dependency_tree(&[
+            ("app", "0.1.0", &["a", "z"]),
+            ("a", "1.0.0", &["x", "d"]),
+            ("x", "1.0.0", &["c"]),
+            ("z", "1.0.0", &["c", "w"]),
+            ("w", "1.0.0", &["v"]),
+            ("v", "1.0.0", &["d"]),
+            ("c", "1.0.0", &[]),
+            ("d", "1.0.0", &[]),
+        ]);
+        let records = records(&["c::f", "d::f"]);
+        let packages = |path_strategy| {
+            let options = ConvertOptions {
+                path_strategy,
+                ..Default::default()
+            };
+            Packages::new(&tree, &records, &options)
+        };
+
+        let shortest = packages(PathStrategy::Shortest);
+        assert_eq!(shortest.get_path("c"), ["app", "z", "c"]);
+        assert_eq!(shortest.get_path("d"), ["app", "a", "d"]);
+
+        // The first discovery in breadth-first order
+        let first = packages(PathStrategy::First);
+        assert_eq!(first.get_path("c"), ["app", "z", "c"]);
+        assert_eq!(first.get_path("d"), ["app", "a", "d"]);
+
+        let longest = packages(PathStrategy::Longest);
+        assert_eq!(longest.get_path("c"), ["app", "a", "x", "c"]);
+        assert_eq!(longest.get_path("d"), ["app", "z", "w", "v", "d"]);
+    }
+
+    #[test]
+    fn test_lockfile_mismatch() {
+        let tree = dependency_tree(&[("other", "0.1.0", &["serde"]), ("serde", "1.0.0", &[])]);
+        let options = ConvertOptions::default();
+        // A lockfile of another project, std crates don't count
+        let other = records(&[
+            "app::main",
+            "foo::f",
+            "bar::b",
+            "std::io::x",
+            "core::fmt::y",
+        ]);
+        let packages = Packages::new(&tree, &other, &options);
+        assert_eq!(packages.lockfile_mismatch(), Some((0, 3)));
+
+        let matching = records(&["other::main", "serde::de::x", "std::io::x"]);
+        let packages = Packages::new(&tree, &matching, &options);
+        assert_eq!(packages.lockfile_mismatch(), None);
+        assert_eq!(Packages::flat(&options).lockfile_mismatch(), None);
+    }
+
+    #[test]
+    fn test_group_by_direct_dep() {
+        // app -> a -> x -> shared, app -> b -> shared
+        let tree = dependency_tree(&[
+            ("app", "0.1.0", &["a", "b"]),
+            ("a", "1.0.0", &["x"]),
+            ("b", "1.0.0", &["shared"]),
+            ("x", "1.0.0", &["shared", "deep"]),
+            ("deep", "1.0.0", &[]),
+            ("shared", "1.0.0", &[]),
+        ]);
+        let records = records(&["app::main", "a::f", "x::f", "deep::f", "shared::f"]);
+        let options = ConvertOptions {
+            group_by_direct_dep: true,
+            ..Default::default()
+        };
+        let packages = Packages::new(&tree, &records, &options);
+        assert_eq!(packages.get_path("app"), ["app"]);
+        assert_eq!(packages.get_path("a"), ["a"]);
+        assert_eq!(packages.get_path("x"), ["a", "x"]);
+        assert_eq!(packages.get_path("deep"), ["a", "deep"]);
+        // The shortest path to the shared crate is through b
+        assert_eq!(packages.get_path("shared"), ["b", "shared"]);
+
+        let packages = Packages::new(&tree, &records, &ConvertOptions::default());
+        assert_eq!(packages.get_path("deep"), ["app", "a", "x", "deep"]);
+    }
+
+    #[test]
+    fn test_versions_report() {
+        let toml = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["rand 0.10.0", "rand 0.7.3", "serde"]
+
+[[package]]
+name = "rand"
+version = "0.10.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "rand"
+version = "0.7.3"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "serde"
+version = "1.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+        let lockfile = Lockfile::from_str(toml).expect("Failed to parse lockfile");
+        let report = versions_report(&lockfile);
+        let rows: Vec<_> = report
+            .crates
+            .iter()
+            .map(|c| {
+                (
+                    c.name.as_str(),
+                    c.version.as_str(),
+                    c.source.as_str(),
+                    c.duplicate,
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("app", "0.1.0", "LOCAL", false),
+                // Sorted by semver, not as strings
+                ("rand", "0.7.3", "REGISTRY", true),
+                ("rand", "0.10.0", "REGISTRY", true),
+                ("serde", "1.0.0", "REGISTRY", false),
+            ]
+        );
+        assert_eq!(report.duplicates().count(), 2);
+        let table = report.to_string();
+        assert!(table.lines().next().expect("header").starts_with("crate"));
+        assert!(table.ends_with("4 packages, 1 crates with several major versions\n"));
+    }
+
+    #[test]
+    fn test_group_by_source() {
+        let toml = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["serde", "forked", "util"]
+
+[[package]]
+name = "serde"
+version = "1.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "forked"
+version = "0.2.0"
+source = "git+https://github.com/example/forked?branch=main#0123456789abcdef0123456789abcdef01234567"
+dependencies = ["serde"]
+
+[[package]]
+name = "util"
+version = "0.1.0"
+"#;
+        let tree = Lockfile::from_str(toml)
+            .expect("Failed to parse lockfile")
+            .dependency_tree()
+            .expect("Failed to build dependency tree");
+        let records = records(&["app::main", "serde::ser", "forked::f", "util::u"]);
+        let options = ConvertOptions {
+            group_by_source: true,
+            ..Default::default()
+        };
+        let packages = Packages::new(&tree, &records, &options);
+        assert_eq!(packages.get_path("app"), ["LOCAL", "app"]);
+        assert_eq!(packages.get_path("util"), ["LOCAL", "app", "util"]);
+        assert_eq!(packages.get_path("serde"), ["REGISTRY", "app", "serde"]);
+        assert_eq!(packages.get_path("forked"), ["GIT", "app", "forked"]);
+
+        let packages = Packages::new(&tree, &records, &ConvertOptions::default());
+        assert_eq!(packages.get_path("serde"), ["app", "serde"]);
+    }
+
+    #[test]
+    fn test_depth_outliers() {
+        // A long chain below a root with many direct dependencies
+        let tree = dependency_tree(&[
+            ("app", "0.1.0", &["s1", "s2", "s3", "s4", "s5", "a"]),
+            ("s1", "1.0.0", &[]),
+            ("s2", "1.0.0", &[]),
+            ("s3", "1.0.0", &[]),
+            ("s4", "1.0.0", &[]),
+            ("s5", "1.0.0", &[]),
+            ("a", "1.0.0", &["b"]),
+            ("b", "1.0.0", &["c"]),
+            ("c", "1.0.0", &["d"]),
+            ("d", "1.0.0", &["e"]),
+            ("e", "1.0.0", &["f"]),
+            ("f", "1.0.0", &["g"]),
+            ("g", "1.0.0", &["h"]),
+            ("h", "1.0.0", &[]),
+        ]);
+        let names = [
+            "app", "s1", "s2", "s3", "s4", "s5", "a", "b", "c", "d", "e", "f", "g", "h",
+        ];
+        let symbols: Vec<String> = names.iter().map(|name| format!("{name}::f")).collect();
+        let symbols: Vec<&str> = symbols.iter().map(String::as_str).collect();
+        let packages = Packages::new(&tree, &records(&symbols), &ConvertOptions::default());
+        assert_eq!(packages.depth_outliers(), (3, vec![("h", 9)]));
+
+        assert_eq!(
+            Packages::flat(&ConvertOptions::default()).depth_outliers(),
+            (0, vec![])
+        );
+    }
+
+    #[test]
+    fn test_reserved_crate_names() {
+        let tree = dependency_tree(&[
+            ("app", "0.1.0", &["sections", "root"]),
+            ("sections", "1.0.0", &[]),
+            ("root", "1.0.0", &[]),
+        ]);
+        let records = records(&["sections::a", "root::b", "unknown::c"]);
+        let packages = Packages::new(&tree, &records, &ConvertOptions::default());
+        assert_eq!(packages.get_path("sections"), ["app", "crate:sections"]);
+        assert_eq!(packages.get_path("root"), ["app", "crate:root"]);
+        assert_eq!(packages.get_path("unknown"), ["crate:unknown"]);
+        assert_eq!(packages.get_path("app"), ["app"]);
+
+        let packages = Packages::flat(&ConvertOptions::default());
+        assert_eq!(packages.crate_name("Sections"), "crate:Sections");
+        assert_eq!(packages.crate_name("serde"), "serde");
+    }
+
+    #[test]
+    fn test_no_roots() {
+        // Every package is a dependency of another one, so there is no root
+        let tree = dependency_tree(&[
+            ("a", "0.1.0", &["b"]),
+            ("b", "0.1.0", &["c"]),
+            ("c", "0.1.0", &["a"]),
+        ]);
+        assert!(tree.roots().is_empty());
+        let records = records(&["a::x", "b::y", "c::z", "std::io::stdio::_print"]);
+        for path_strategy in [
+            PathStrategy::Shortest,
+            PathStrategy::First,
+            PathStrategy::Longest,
+        ] {
+            let options = ConvertOptions {
+                path_strategy,
+                ..Default::default()
+            };
+            let packages = Packages::new(&tree, &records, &options);
+            for name in ["a", "b", "c"] {
+                let path = packages.get_path(name);
+                assert_eq!(path.last().map(String::as_str), Some(name), "{path:?}");
+            }
+            assert_eq!(packages.get_path("std"), ["std"]);
+        }
+        let packages = Packages::new(&tree, &records, &ConvertOptions::default());
+        assert_eq!(packages.get_path("b"), ["b"]);
+    }
+
+    #[test]
+    fn test_group_by_version_major() {
+        let tree = dependency_tree(&[
+            ("app", "0.1.0", &["rand 0.8.5", "foo 1.0.0"]),
+            ("foo", "1.0.0", &["rand 0.9.0", "bar"]),
+            ("bar", "1.0.0", &[]),
+            ("rand", "0.8.5", &[]),
+            ("rand", "0.9.0", &[]),
+        ]);
+        let records = records(&["rand::f", "bar::f"]);
+
+        let packages = Packages::new(&tree, &records, &ConvertOptions::default());
+        assert_eq!(packages.get_path("rand"), ["app", "rand"]);
+
+        let options = ConvertOptions {
+            group_by_version_major: true,
+            ..Default::default()
+        };
+        let packages = Packages::new(&tree, &records, &options);
+        assert_eq!(packages.get_path("rand"), ["app", "rand v0.8"]);
+        // Crates with a single major version keep their name
+        assert_eq!(packages.get_path("bar"), ["app", "foo", "bar"]);
+
+        // Each major version is found from the compile unit
+        let unit = |dir: &str| {
+            vec![format!(
+                "/home/u/.cargo/registry/src/index.crates.io-1/{dir}/src/lib.rs"
+            )]
+        };
+        assert_eq!(
+            packages.get_path_in("rand", &unit("rand-0.9.0")),
+            ["app", "foo", "rand v0.9"]
+        );
+        assert_eq!(
+            packages.get_path_in("rand", &unit("rand-0.8.5")),
+            ["app", "rand v0.8"]
+        );
+        assert_eq!(
+            packages.get_path_in("rand", &unit("random-0.9.0")),
+            ["app", "rand v0.8"]
+        );
+        assert_eq!(
+            packages.get_path_in("bar", &unit("bar-1.0.0")),
+            ["app", "foo", "bar"]
+        );
+    }
 }
@@ -0,0 +1,261 @@
+use crate::tree::Node;
+use std::collections::HashMap;
+
+// Field numbers of https://github.com/google/pprof/blob/main/proto/profile.proto
+
+/// Profile fields
+const PROFILE_SAMPLE_TYPE: u32 = 1;
+const PROFILE_SAMPLE: u32 = 2;
+const PROFILE_LOCATION: u32 = 4;
+const PROFILE_FUNCTION: u32 = 5;
+const PROFILE_STRING_TABLE: u32 = 6;
+/// ValueType fields
+const VALUE_TYPE_TYPE: u32 = 1;
+const VALUE_TYPE_UNIT: u32 = 2;
+/// Sample fields
+const SAMPLE_LOCATION_ID: u32 = 1;
+const SAMPLE_VALUE: u32 = 2;
+/// Location fields
+const LOCATION_ID: u32 = 1;
+const LOCATION_LINE: u32 = 4;
+/// Line fields
+const LINE_FUNCTION_ID: u32 = 1;
+/// Function fields
+const FUNCTION_ID: u32 = 1;
+const FUNCTION_NAME: u32 = 2;
+
+/// Protobuf wire types
+const WIRE_VARINT: u32 = 0;
+const WIRE_LEN: u32 = 2;
+
+/// Protobuf message writer
+#[derive(Default)]
+struct Message {
+    buf: Vec<u8>,
+}
+
+impl Message {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire: u32) {
+        self.varint(u64::from(field << 3 | wire));
+    }
+
+    /// A varint field, zero values are left out like proto3 defaults
+    fn uint(&mut self, field: u32, value: u64) {
+        if value != 0 {
+            self.key(field, WIRE_VARINT);
+            self.varint(value);
+        }
+    }
+
+    fn bytes(&mut self, field: u32, bytes: &[u8]) {
+        self.key(field, WIRE_LEN);
+        self.varint(bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn message(&mut self, field: u32, message: Message) {
+        self.bytes(field, &message.buf);
+    }
+
+    /// A packed repeated varint field
+    fn packed(&mut self, field: u32, values: &[u64]) {
+        let mut packed = Message::default();
+        for value in values {
+            packed.varint(*value);
+        }
+        self.message(field, packed);
+    }
+}
+
+/// Builds the string table, functions and locations of a profile
+/// Every distinct name is one function with one location, both with the id of the string
+#[derive(Default)]
+struct Profile {
+    strings: Vec<String>,
+    ids: HashMap<String, u64>,
+    samples: Message,
+}
+
+impl Profile {
+    fn new() -> Self {
+        // The first string must be empty
+        let mut profile = Profile::default();
+        profile.strings.push(String::new());
+        profile
+    }
+
+    fn string(&mut self, s: &str) -> u64 {
+        if let Some(id) = self.ids.get(s) {
+            return *id;
+        }
+        let id = self.strings.len() as u64;
+        self.strings.push(s.to_string());
+        self.ids.insert(s.to_string(), id);
+        id
+    }
+
+    /// Add a sample, `stack` is ordered from the root to the leaf
+    fn sample(&mut self, stack: &[&str], value: u64) {
+        // pprof stacks start at the leaf
+        let locations: Vec<u64> = stack.iter().rev().map(|name| self.string(name)).collect();
+        let mut sample = Message::default();
+        sample.packed(SAMPLE_LOCATION_ID, &locations);
+        sample.packed(SAMPLE_VALUE, &[value]);
+        self.samples.message(PROFILE_SAMPLE, sample);
+    }
+
+    fn encode(mut self) -> Vec<u8> {
+        let names = self.strings.len() as u64;
+        let size = self.string("size");
+        let bytes = self.string("bytes");
+
+        let mut profile = Message::default();
+        let mut sample_type = Message::default();
+        sample_type.uint(VALUE_TYPE_TYPE, size);
+        sample_type.uint(VALUE_TYPE_UNIT, bytes);
+        profile.message(PROFILE_SAMPLE_TYPE, sample_type);
+        profile.buf.extend_from_slice(&self.samples.buf);
+
+        // The names in stacks are strings 1..names, see `Profile::sample`
+        for id in 1..names {
+            let mut line = Message::default();
+            line.uint(LINE_FUNCTION_ID, id);
+            let mut location = Message::default();
+            location.uint(LOCATION_ID, id);
+            location.message(LOCATION_LINE, line);
+            profile.message(PROFILE_LOCATION, location);
+
+            let mut function = Message::default();
+            function.uint(FUNCTION_ID, id);
+            function.uint(FUNCTION_NAME, id);
+            profile.message(PROFILE_FUNCTION, function);
+        }
+        for s in &self.strings {
+            profile.bytes(PROFILE_STRING_TABLE, s.as_bytes());
+        }
+        profile.buf
+    }
+}
+
+/// Encode the tree below `root` as an uncompressed pprof profile
+/// Each node with an own file size is a sample, its stack is the path of the node
+/// and its value the file size, with the sample type `size` in `bytes`
+pub(crate) fn encode(root: &Node) -> Vec<u8> {
+    let mut profile = Profile::new();
+    let mut stack = Vec::new();
+    for child in sorted_children(root) {
+        add_samples(child, &mut stack, &mut profile);
+    }
+    profile.encode()
+}
+
+fn sorted_children(node: &Node) -> Vec<&Node> {
+    let mut children: Vec<_> = node.nodes.values().collect();
+    children.sort_by(|a, b| a.name.cmp(&b.name));
+    children
+}
+
+fn add_samples<'a>(node: &'a Node, stack: &mut Vec<&'a str>, profile: &mut Profile) {
+    stack.push(&node.name);
+    if node.filesize > 0 {
+        profile.sample(stack, node.filesize);
+    }
+    for child in sorted_children(node) {
+        add_samples(child, stack, profile);
+    }
+    stack.pop();
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Message, PROFILE_SAMPLE, PROFILE_STRING_TABLE};
+    use crate::{ConvertOptions, Tree};
+
+    /// Split a message into `(field, payload)`, varints are returned as their encoding
+    fn fields(mut buf: &[u8]) -> Vec<(u32, Vec<u8>)> {
+        fn varint(buf: &mut &[u8]) -> u64 {
+            let mut value = 0;
+            let mut shift = 0;
+            loop {
+                let byte = buf[0];
+                *buf = &buf[1..];
+                value |= u64::from(byte & 0x7f) << shift;
+                shift += 7;
+                if byte < 0x80 {
+                    return value;
+                }
+            }
+        }
+        let mut fields = Vec::new();
+        while !buf.is_empty() {
+            let key = varint(&mut buf) as u32;
+            let payload = match key & 7 {
+                0 => {
+                    let mut m = Message::default();
+                    m.varint(varint(&mut buf));
+                    m.buf
+                }
+                2 => {
+                    let len = varint(&mut buf) as usize;
+                    let (payload, rest) = buf.split_at(len);
+                    buf = rest;
+                    payload.to_vec()
+                }
+                wire => panic!("unexpected wire type {wire}"),
+            };
+            fields.push((key >> 3, payload));
+        }
+        fields
+    }
+
+    #[test]
+    fn test_varint() {
+        let mut m = Message::default();
+        m.varint(1);
+        m.varint(300);
+        assert_eq!(m.buf, [0x01, 0xac, 0x02]);
+    }
+
+    #[test]
+    fn test_encode() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,1,1
+.text,foo::b,300,300
+.text,bar::c,4,4
+"#;
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        let profile = fields(&tree.to_pprof());
+
+        let strings: Vec<_> = profile
+            .iter()
+            .filter(|(field, _)| *field == PROFILE_STRING_TABLE)
+            .map(|(_, s)| String::from_utf8(s.clone()).expect("utf8"))
+            .collect();
+        assert_eq!(strings[0], "");
+        for name in ["foo", "bar", ".text", "a", "b", "c", "size", "bytes"] {
+            assert!(strings.iter().any(|s| s == name), "{name}");
+        }
+
+        let samples: Vec<_> = profile
+            .iter()
+            .filter(|(field, _)| *field == PROFILE_SAMPLE)
+            .map(|(_, sample)| fields(sample))
+            .collect();
+        assert_eq!(samples.len(), 3);
+        // bar/.text/c, with the leaf first
+        let id = |name: &str| strings.iter().position(|s| s == name).expect("string") as u8;
+        assert_eq!(samples[0][0].1, [id("c"), id(".text"), id("bar")]);
+        assert_eq!(samples[0][1].1, [4]);
+        // foo/.text/b is 300 bytes
+        assert_eq!(samples[2][1].1, [0xac, 0x02]);
+    }
+}
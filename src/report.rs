@@ -0,0 +1,264 @@
+use serde::Serialize;
+use std::{collections::HashSet, fmt};
+
+/// Self-contained HTML treemap, `{{DATA}}` is replaced by the nested JSON tree
+const HTML_TEMPLATE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>bloaty-metafile</title>
+<style>
+  body { margin: 0; font: 12px sans-serif; }
+  #path { padding: 6px; background: #333; color: #fff; cursor: pointer; }
+  #map { position: relative; width: 100vw; height: calc(100vh - 28px); }
+  .node { position: absolute; box-sizing: border-box; overflow: hidden; border: 1px solid #fff; padding: 2px; cursor: pointer; }
+</style>
+</head>
+<body>
+<div id="path"></div>
+<div id="map"></div>
+<script>
+const data = {{DATA}};
+const colors = ["#8dd3c7", "#ffffb3", "#bebada", "#fb8072", "#80b1d3", "#fdb462", "#b3de69", "#fccde5"];
+
+function total(node) {
+  node.total = node.filesize + node.children.reduce((sum, child) => sum + total(child), 0);
+  node.children.sort((a, b) => b.total - a.total);
+  return node.total;
+}
+
+function size(bytes) {
+  const units = ["B", "KB", "MB", "GB"];
+  let i = 0;
+  while (bytes >= 1024 && i < units.length - 1) { bytes /= 1024; i++; }
+  return (i ? bytes.toFixed(1) : bytes) + " " + units[i];
+}
+
+// Slice-and-dice layout, alternating direction per level
+function layout(node, x, y, w, h, depth, parent) {
+  const el = document.createElement("div");
+  el.className = "node";
+  Object.assign(el.style, { left: x + "px", top: y + "px", width: w + "px", height: h + "px" });
+  el.style.background = colors[depth % colors.length];
+  el.title = node.name + " " + size(node.total);
+  el.textContent = node.name + " " + size(node.total);
+  el.onclick = (e) => { e.stopPropagation(); stack.push(node); render(); };
+  parent.appendChild(el);
+  if (depth >= 2 || w < 20 || h < 20 || !node.total) return;
+  let offset = 0;
+  const inner = { x: 2, y: 16, w: w - 4, h: h - 18 };
+  for (const child of node.children) {
+    const ratio = child.total / node.total;
+    if (depth % 2 === 0) {
+      layout(child, inner.x + offset, inner.y, inner.w * ratio, inner.h, depth + 1, el);
+      offset += inner.w * ratio;
+    } else {
+      layout(child, inner.x, inner.y + offset, inner.w, inner.h * ratio, depth + 1, el);
+      offset += inner.h * ratio;
+    }
+  }
+}
+
+const stack = [data];
+function render() {
+  const map = document.getElementById("map");
+  const current = stack[stack.length - 1];
+  map.innerHTML = "";
+  document.getElementById("path").textContent = stack.map((n) => n.name).join(" / ") + " (" + size(current.total) + ")";
+  layout(current, 0, 0, map.clientWidth, map.clientHeight, 0, map);
+}
+
+document.getElementById("path").onclick = () => { if (stack.length > 1) { stack.pop(); render(); } };
+window.onresize = render;
+total(data);
+render();
+</script>
+</body>
+</html>
+"##;
+
+/// Format a byte count with a binary unit, e.g. `845 B`, `1.2 KB`, `3.0 MB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    // Compare the rounded size, 1048575 bytes is `1.0 MB` rather than `1024.0 KB`
+    while (size * 10.0).round() >= 10240.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// A size in a text report: raw bytes, or [`format_size`] if `human`
+/// Every report formats its sizes this way, `human` is the alternate flag (`{:#}`)
+pub(crate) fn size_cell(bytes: u64, human: bool) -> String {
+    if human {
+        format_size(bytes)
+    } else {
+        bytes.to_string()
+    }
+}
+
+/// Write a header and rows with a left aligned name column and right aligned size columns
+pub(crate) fn write_table<const N: usize>(
+    f: &mut fmt::Formatter<'_>,
+    header: [&str; N],
+    rows: &[[String; N]],
+) -> fmt::Result {
+    let header = header.map(String::from);
+    let widths: [usize; N] = std::array::from_fn(|i| {
+        std::iter::once(&header)
+            .chain(rows)
+            .map(|row| row[i].len())
+            .max()
+            .unwrap_or(0)
+    });
+    for row in std::iter::once(&header).chain(rows) {
+        for (i, cell) in row.iter().enumerate() {
+            if i == 0 {
+                write!(f, "{cell:<w$}", w = widths[i])?;
+            } else {
+                write!(f, "  {cell:>w$}", w = widths[i])?;
+            }
+        }
+        writeln!(f)?;
+    }
+    Ok(())
+}
+
+/// Symbol count and file size of a crate, see `Tree::crate_stats`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CrateStats {
+    pub name: String,
+    pub symbols: u64,
+    pub bytes: u64,
+}
+
+impl CrateStats {
+    /// Average file size per symbol, rounded down (0 without symbols)
+    pub fn average(&self) -> u64 {
+        self.bytes.checked_div(self.symbols).unwrap_or(0)
+    }
+}
+
+/// Per crate symbol statistics, sorted by size (largest first), then by name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StatsReport {
+    pub crates: Vec<CrateStats>,
+}
+
+/// A `crate symbols bytes average` table
+impl fmt::Display for StatsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let human = f.alternate();
+        let rows: Vec<[String; 4]> = self
+            .crates
+            .iter()
+            .map(|stats| {
+                [
+                    stats.name.clone(),
+                    stats.symbols.to_string(),
+                    size_cell(stats.bytes, human),
+                    size_cell(stats.average(), human),
+                ]
+            })
+            .collect();
+        write_table(f, ["crate", "symbols", "bytes", "average"], &rows)
+    }
+}
+
+/// A package in Cargo.lock, see `versions_report`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CrateVersion {
+    pub name: String,
+    pub version: String,
+    /// Where the package comes from, `REGISTRY`, `GIT` or `LOCAL`
+    pub source: String,
+    /// The crate has several semver incompatible versions in the lockfile
+    pub duplicate: bool,
+}
+
+/// Every package in Cargo.lock, sorted by name and version
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct VersionsReport {
+    pub crates: Vec<CrateVersion>,
+}
+
+impl VersionsReport {
+    /// Packages of crates with several semver incompatible versions
+    pub fn duplicates(&self) -> impl Iterator<Item = &CrateVersion> {
+        self.crates.iter().filter(|c| c.duplicate)
+    }
+}
+
+/// A left aligned `crate version source duplicate` table
+impl fmt::Display for VersionsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = ["crate", "version", "source", "duplicate"];
+        let rows: Vec<[&str; 4]> = std::iter::once(header)
+            .chain(self.crates.iter().map(|c| {
+                [
+                    c.name.as_str(),
+                    c.version.as_str(),
+                    c.source.as_str(),
+                    if c.duplicate { "yes" } else { "" },
+                ]
+            }))
+            .collect();
+        let width = |i: usize| rows.iter().map(|row| row[i].len()).max().unwrap_or(0);
+        let (w0, w1, w2) = (width(0), width(1), width(2));
+        for [name, version, source, duplicate] in &rows {
+            let line = format!("{name:<w0$}  {version:<w1$}  {source:<w2$}  {duplicate}");
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        let crates: HashSet<&str> = self.duplicates().map(|c| c.name.as_str()).collect();
+        writeln!(
+            f,
+            "{} packages, {} crates with several major versions",
+            self.crates.len(),
+            crates.len()
+        )
+    }
+}
+
+/// Render the nested JSON tree (see `Tree::to_nested_json`) as a self-contained HTML treemap
+pub fn to_html(nested: &serde_json::Value) -> String {
+    // Escape `</` so names like `</script>` can't close the script tag
+    let data = nested.to_string().replace("</", "<\\/");
+    HTML_TEMPLATE.replace("{{DATA}}", &data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_size, to_html};
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1024), "1.0 KB");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(1048575), "1.0 MB");
+        assert_eq!(format_size(1048063), "1023.5 KB");
+        assert_eq!(format_size(1048576), "1.0 MB");
+        assert_eq!(format_size(1 << 30), "1.0 GB");
+    }
+
+    #[test]
+    fn test_to_html() {
+        let nested = serde_json::json!({
+            "name": "ROOT",
+            "vmsize": 0,
+            "filesize": 0,
+            "children": [{ "name": "</script>", "vmsize": 1, "filesize": 1, "children": [] }],
+        });
+        let html = to_html(&nested);
+        assert!(!html.contains("{{DATA}}"));
+        assert!(html.contains(r#""name":"ROOT""#));
+        assert_eq!(html.matches("</script>").count(), 1);
+    }
+}
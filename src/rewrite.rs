@@ -0,0 +1,237 @@
+use crate::{
+    error::{BloatyError, Result},
+    tree::SectionRecord,
+};
+use regex::Regex;
+use std::{borrow::Cow, str::FromStr};
+
+/// Custom rule applied to the path of every record before it is added to the tree
+///
+/// `path` is the resolved path (dependency path, section and symbol parts), `record` the
+/// CSV record it was built from. An empty path after rewriting drops the record.
+///
+/// # Example
+///
+/// ```
+/// use bloaty_metafile::{PathRewriter, SectionRecord};
+///
+/// /// Put every `.rodata` record into a single DATA bucket
+/// struct RodataBucket;
+///
+/// impl PathRewriter for RodataBucket {
+///     fn rewrite(&self, path: &mut Vec<String>, record: &SectionRecord) {
+///         if record.sections == ".rodata" {
+///             *path = vec!["DATA".to_string(), record.symbols.clone()];
+///         }
+///     }
+/// }
+/// ```
+pub trait PathRewriter {
+    fn rewrite(&self, path: &mut Vec<String>, record: &SectionRecord);
+}
+
+/// Merge the top-level `core` and `alloc` nodes into `std`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MergeStd;
+
+impl PathRewriter for MergeStd {
+    fn rewrite(&self, path: &mut Vec<String>, _record: &SectionRecord) {
+        if let Some(first) = path.first_mut()
+            && (first == "core" || first == "alloc")
+        {
+            *first = "std".to_string();
+        }
+    }
+}
+
+/// A sed style `s/pattern/replacement/` rule applied to symbols before they are split into a path
+/// The replacement can refer to capture groups as `$1` or `${name}`, every match is replaced
+#[derive(Debug, Clone)]
+pub struct SymbolRegex {
+    regex: Regex,
+    replacement: String,
+}
+
+impl SymbolRegex {
+    pub fn new(pattern: &str, replacement: &str) -> Result<Self> {
+        let regex = Regex::new(pattern).map_err(|e| BloatyError::InvalidSymbolRegex {
+            rule: pattern.to_string(),
+            reason: e.to_string(),
+        })?;
+        Ok(Self {
+            regex,
+            replacement: replacement.to_string(),
+        })
+    }
+
+    /// Apply the rule to a symbol, borrowing it if nothing matched
+    pub fn apply<'a>(&self, symbol: &'a str) -> Cow<'a, str> {
+        self.regex.replace_all(symbol, self.replacement.as_str())
+    }
+}
+
+/// Parse `s/pattern/replacement/`, any character after `s` can be the delimiter and is
+/// escaped with a backslash, the only allowed flag is `g` (always on)
+impl FromStr for SymbolRegex {
+    type Err = BloatyError;
+
+    fn from_str(rule: &str) -> Result<Self> {
+        let invalid = |reason: &str| BloatyError::InvalidSymbolRegex {
+            rule: rule.to_string(),
+            reason: reason.to_string(),
+        };
+        let mut chars = rule.chars();
+        if chars.next() != Some('s') {
+            return Err(invalid("expected s/pattern/replacement/"));
+        }
+        let delimiter = chars.next().ok_or_else(|| invalid("missing delimiter"))?;
+        let mut parts = vec![String::new()];
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some(next) if next == delimiter => parts.last_mut().unwrap().push(next),
+                    Some(next) => parts.last_mut().unwrap().extend(['\\', next]),
+                    None => parts.last_mut().unwrap().push(c),
+                },
+                c if c == delimiter => parts.push(String::new()),
+                c => parts.last_mut().unwrap().push(c),
+            }
+        }
+        match parts.as_slice() {
+            [pattern, replacement, flags] if flags.is_empty() || flags == "g" => {
+                SymbolRegex::new(pattern, replacement)
+            }
+            [_, _, _] => Err(invalid("unsupported flags")),
+            _ => Err(invalid("expected s/pattern/replacement/")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MergeStd, PathRewriter, SymbolRegex};
+    use crate::{
+        options::{ConvertOptions, Weight},
+        tree::SectionRecord,
+        tree::Tree,
+    };
+
+    #[test]
+    fn test_symbol_regex() {
+        let rule: SymbolRegex = "s/^my_company_//".parse().expect("valid rule");
+        assert_eq!(rule.apply("my_company_app::main"), "app::main");
+        assert_eq!(rule.apply("other::my_company_x"), "other::my_company_x");
+
+        let rule: SymbolRegex = r"s|__generated_(\w+)|gen::$1|".parse().expect("valid rule");
+        assert_eq!(rule.apply("app::__generated_abc::f"), "app::gen::abc::f");
+
+        let rule: SymbolRegex = r"s/a\/b/c/".parse().expect("valid rule");
+        assert_eq!(rule.apply("xa/b"), "xc");
+
+        assert!("s/(/x/".parse::<SymbolRegex>().is_err());
+        assert!("s/a/b".parse::<SymbolRegex>().is_err());
+        assert!("y/a/b/".parse::<SymbolRegex>().is_err());
+    }
+
+    #[test]
+    fn test_symbol_regex_rules() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,acme_app::__generated_routes::get,10,10
+.text,acme_db::query,20,20
+"#;
+        let options = ConvertOptions {
+            no_lock: true,
+            // Applied in order, the second rule sees the result of the first
+            symbol_regex: vec![
+                "s/^acme_//".parse().expect("valid rule"),
+                "s/__generated_(\\w+)/$1/".parse().expect("valid rule"),
+            ],
+            ..Default::default()
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        let meta = tree.to_metafile("BINARY", &options);
+        assert_eq!(meta.inputs["app/.text/routes/get"].bytes, 10);
+        assert_eq!(meta.inputs["db/.text/query"].bytes, 20);
+    }
+
+    #[test]
+    fn test_merge_std() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,core::fmt::write,10,10
+.text,alloc::vec::from_elem,20,20
+.text,std::io::stdio::_print,30,30
+.text,foo::core::f,40,40
+"#;
+        let options = ConvertOptions::default();
+        let tree =
+            Tree::new_with_rewriter(csv, &options, &MergeStd).expect("Failed to create tree");
+        let meta = tree.to_metafile("BINARY", &options);
+        assert!(meta.inputs.contains_key("std/.text/fmt/write"));
+        assert!(meta.inputs.contains_key("std/.text/vec/from_elem"));
+        assert!(
+            !meta
+                .inputs
+                .keys()
+                .any(|k| k.starts_with("core") || k.starts_with("alloc"))
+        );
+        assert!(meta.inputs.contains_key("foo/.text/core/f"));
+    }
+
+    #[test]
+    fn test_no_sections_before_rewrite() {
+        struct RodataBucket;
+        impl PathRewriter for RodataBucket {
+            fn rewrite(&self, path: &mut Vec<String>, record: &SectionRecord) {
+                if record.sections == ".rodata" {
+                    *path = vec!["DATA".to_string(), record.symbols.clone()];
+                }
+            }
+        }
+
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,10,10
+.rodata,foo::b,20,20
+.rodata,[section .rodata],30,30
+.rodata,anon.1234,40,40
+"#;
+        let options = ConvertOptions {
+            no_lock: true,
+            no_sections: true,
+            ..Default::default()
+        };
+        let tree =
+            Tree::new_with_rewriter(csv, &options, &RodataBucket).expect("Failed to create tree");
+        let meta = tree.to_metafile("BINARY", &options);
+        // The section remainder and the symbol without a crate are dropped although the
+        // rewriter moved them to DATA, foo::b stays attributed to foo
+        assert_eq!(meta.outputs["BINARY"].bytes, 30);
+        assert!(meta.inputs.contains_key("DATA/foo::b"));
+        assert_eq!(tree.crate_totals(Weight::Bytes), [("foo".to_string(), 30)]);
+    }
+
+    #[test]
+    fn test_drop_empty_path() {
+        struct DropRodata;
+        impl PathRewriter for DropRodata {
+            fn rewrite(&self, path: &mut Vec<String>, record: &SectionRecord) {
+                if record.sections == ".rodata" {
+                    path.clear();
+                }
+            }
+        }
+
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,10,10
+.rodata,foo::b,20,20
+"#;
+        let options = ConvertOptions::default();
+        let tree =
+            Tree::new_with_rewriter(csv, &options, &DropRodata).expect("Failed to create tree");
+        let meta = tree.to_metafile("BINARY", &options);
+        assert_eq!(meta.outputs["BINARY"].bytes, 10);
+    }
+}
@@ -0,0 +1,76 @@
+use serde::Serialize;
+use serde_metafile::Metafile;
+
+/// FNV-1a 64-bit offset basis and prime
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// An input of a metafile with a stable numeric id, for tools that reference inputs by a
+/// compact key instead of the path
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InputSummary {
+    pub path: String,
+    /// See [`input_id`]
+    pub id: u64,
+    pub bytes: u64,
+}
+
+/// Stable id of an input path, the 64-bit FNV-1a hash of its UTF-8 bytes
+/// Unlike `std`'s hashers it is the same across runs, platforms and versions
+pub fn input_id(path: &str) -> u64 {
+    path.bytes().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// The inputs of a metafile with their ids, sorted by path
+pub fn summarize_inputs(meta: &Metafile) -> Vec<InputSummary> {
+    let mut inputs: Vec<InputSummary> = meta
+        .inputs
+        .iter()
+        .map(|(path, input)| InputSummary {
+            path: path.clone(),
+            id: input_id(path),
+            bytes: input.bytes,
+        })
+        .collect();
+    inputs.sort_by(|a, b| a.path.cmp(&b.path));
+    inputs
+}
+
+#[cfg(test)]
+mod test {
+    use super::{input_id, summarize_inputs};
+    use crate::{ConvertOptions, Tree};
+
+    #[test]
+    fn test_input_id() {
+        // Fixed values, so ids stay the same across runs and releases
+        assert_eq!(input_id(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(input_id("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(input_id("foo/.text/a"), input_id("foo/.text/a"));
+        assert_ne!(input_id("foo/.text/a"), input_id("foo/.text/b"));
+    }
+
+    #[test]
+    fn test_summarize_inputs() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,10,10
+.text,foo::b,20,20
+"#;
+        let options = ConvertOptions::default();
+        let meta = Tree::new(csv, &options)
+            .expect("Failed to create tree")
+            .to_metafile("BINARY", &options);
+        let inputs = summarize_inputs(&meta);
+        assert_eq!(inputs.len(), meta.inputs.len());
+        let a = inputs
+            .iter()
+            .find(|input| input.path == "foo/.text/a")
+            .expect("input");
+        assert_eq!(a.id, input_id("foo/.text/a"));
+        assert_eq!(a.bytes, 10);
+        assert!(inputs.windows(2).all(|w| w[0].path < w[1].path));
+    }
+}
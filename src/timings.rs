@@ -0,0 +1,60 @@
+use std::{fmt, time::Duration};
+
+/// Wall-clock duration of each conversion phase, see [`crate::Tree::timings`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timings {
+    /// Parsing the CSV into records
+    pub parse: Duration,
+    /// Loading Cargo.lock and resolving the dependency paths of crates
+    pub lockfile: Duration,
+    /// Attributing the records and building the tree
+    pub build: Duration,
+    /// Converting the tree to the output format
+    pub convert: Duration,
+    /// Serializing the output to a string
+    pub serialize: Duration,
+}
+
+impl Timings {
+    /// Sum of all phases
+    pub fn total(&self) -> Duration {
+        self.parse + self.lockfile + self.build + self.convert + self.serialize
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let phases = [
+            ("parse", self.parse),
+            ("lockfile", self.lockfile),
+            ("build", self.build),
+            ("convert", self.convert),
+            ("serialize", self.serialize),
+            ("total", self.total()),
+        ];
+        for (name, duration) in phases {
+            writeln!(f, "{name:<10} {:>10.3} ms", duration.as_secs_f64() * 1000.0)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Timings;
+    use std::time::Duration;
+
+    #[test]
+    fn test_display() {
+        let timings = Timings {
+            parse: Duration::from_micros(1500),
+            build: Duration::from_millis(20),
+            ..Default::default()
+        };
+        assert_eq!(timings.total(), Duration::from_micros(21500));
+        let s = timings.to_string();
+        assert!(s.contains("parse           1.500 ms\n"), "{s}");
+        assert!(s.contains("build          20.000 ms\n"), "{s}");
+        assert!(s.ends_with("total          21.500 ms\n"), "{s}");
+    }
+}
@@ -1,8 +1,250 @@
-use crate::packages::Packages;
+use crate::{options::ConvertOptions, packages::Packages};
+use std::{borrow::Cow, collections::HashMap};
 
 pub const ROOT_NAME: &str = "ROOT";
 pub const UNKNOWN_NAME: &str = "UNKNOWN";
 pub const SECTIONS_NAME: &str = "SECTIONS";
+pub const RUNTIME_NAME: &str = "RUNTIME";
+pub const OTHERS_NAME: &str = "OTHERS";
+pub const FEATURE_PREFIX: &str = "FEATURE:";
+pub const COMPONENT_PREFIX: &str = "COMPONENT:";
+pub const FOLDED_NAME: &str = "[others]";
+pub const OVERFLOW_NAME: &str = "[overflow]";
+pub const NO_SECTION_NAME: &str = "[no-section]";
+pub const ENTRY_NAME: &str = "ENTRY";
+pub const REGISTRY_NAME: &str = "REGISTRY";
+pub const GIT_NAME: &str = "GIT";
+pub const LOCAL_NAME: &str = "LOCAL";
+pub const PADDING_NAME: &str = "[padding]";
+pub const UNATTRIBUTED_NAME: &str = "[unattributed]";
+pub const PROC_MACRO_NAME: &str = "[proc-macro]";
+pub const CRATE_PREFIX: &str = "crate:";
+
+/// Bucket names sharing the top level with crates, crates with these names (in any case) are
+/// shown as `crate:<name>`
+const RESERVED_NAMES: &[&str] = &[
+    ROOT_NAME,
+    UNKNOWN_NAME,
+    SECTIONS_NAME,
+    RUNTIME_NAME,
+    OTHERS_NAME,
+    ENTRY_NAME,
+    REGISTRY_NAME,
+    GIT_NAME,
+    LOCAL_NAME,
+];
+
+/// Node name of a crate, `crate:sections` for a crate named like a bucket (`SECTIONS`)
+#[inline]
+pub fn crate_node_name(name: &str) -> Cow<'_, str> {
+    if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(name)) {
+        Cow::Owned(format!("{CRATE_PREFIX}{name}"))
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
+/// Standard library crates, never treated as the instantiating crate of generic code
+pub(crate) const STD_CRATES: &[&str] = &["std", "core", "alloc", "proc_macro", "test"];
+
+/// Compiler builtin and runtime symbols without a crate prefix
+const RUNTIME_SYMBOLS: &[&str] = &[
+    "memcpy",
+    "memmove",
+    "memset",
+    "memcmp",
+    "bcmp",
+    "strlen",
+    "__udivdi3",
+    "__divdi3",
+    "__umoddi3",
+    "__moddi3",
+    "__udivti3",
+    "__divti3",
+    "__umodti3",
+    "__modti3",
+    "__muloti4",
+    "rust_eh_personality",
+];
+
+/// Entry point and startup symbols (ELF, Mach-O, PE)
+const ENTRY_SYMBOLS: &[&str] = &[
+    "main",
+    "_main",
+    "_start",
+    "start",
+    "__libc_start_main",
+    "__libc_csu_init",
+    "__libc_csu_fini",
+    "_init",
+    "_fini",
+    "mainCRTStartup",
+    "WinMainCRTStartup",
+    "rust_begin_unwind",
+];
+
+/// Functions of Rust's startup code in `std::rt`
+const ENTRY_RT_FUNCTIONS: &[&str] = &["lang_start", "lang_start_internal"];
+
+/// Symbol prefixes of compiler builtin and runtime symbols
+const RUNTIME_PREFIXES: &[&str] = &["__rust_", "__builtin_"];
+
+/// Platform specific section names (ELF, Mach-O, PE) to canonical names
+const CANONICAL_SECTIONS: &[(&str, &str)] = &[
+    (".text", "text"),
+    ("__text", "text"),
+    (".rodata", "rodata"),
+    (".rdata", "rodata"),
+    ("__const", "rodata"),
+    ("__cstring", "rodata"),
+    (".data", "data"),
+    ("__data", "data"),
+    (".bss", "bss"),
+    ("__bss", "bss"),
+    ("__common", "bss"),
+    (".tbss", "tbss"),
+    ("__thread_bss", "tbss"),
+    (".tdata", "tdata"),
+    ("__thread_data", "tdata"),
+    (".eh_frame", "eh_frame"),
+    ("__eh_frame", "eh_frame"),
+    (".pdata", "eh_frame"),
+    (".xdata", "eh_frame"),
+];
+
+/// Kind of the symbols in a section, by canonical section name (see `canonical_section`)
+const SECTION_KINDS: &[(&str, &str)] = &[
+    ("text", "code"),
+    ("rodata", "rodata"),
+    ("data", "data"),
+    ("tdata", "data"),
+    ("bss", "bss"),
+    ("tbss", "bss"),
+    ("eh_frame", "unwind"),
+    (".eh_frame_hdr", "unwind"),
+    (".gcc_except_table", "unwind"),
+    ("__unwind_info", "unwind"),
+    ("__gcc_except_tab", "unwind"),
+];
+
+/// Kind of the symbols in a leaf merged from sections of different kinds
+pub const MIXED_KIND: &str = "mixed";
+
+/// Kind of the symbols in a section: `code`, `rodata`, `data`, `bss` or `unwind`
+/// None for other sections like debug info or symbol tables
+pub fn section_kind(section: &str) -> Option<&'static str> {
+    let section = canonical_section(merge_pe_section(section));
+    let name = mach_o_section_name(section).unwrap_or(section);
+    SECTION_KINDS
+        .iter()
+        .find(|(from, _)| *from == name)
+        .map(|(_, kind)| *kind)
+}
+
+/// The section part of a Mach-O `__SEG,__sect` name, e.g. `__text` for `__TEXT,__text`
+/// None for anything else, so a custom `#[link_section = "foo,bar"]` is not split
+fn mach_o_section_name(section: &str) -> Option<&str> {
+    let is_part = |s: &str| {
+        s.len() > 2
+            && s.starts_with("__")
+            && s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+    };
+    let (segment, name) = section.trim().split_once(',')?;
+    (is_part(segment) && is_part(name)).then_some(name)
+}
+
+/// Escape the characters of a section name or dimension value that can't be part of a node
+/// name, e.g. from `#[link_section = "my data"]` or a compile unit path: `/`, `%`, whitespace
+/// and control characters become `%XX`
+pub fn sanitize_node_name(name: &str) -> Cow<'_, str> {
+    let is_unsafe = |c: char| c == '/' || c == '%' || c.is_whitespace() || c.is_control();
+    if !name.contains(is_unsafe) {
+        return Cow::Borrowed(name);
+    }
+    let mut escaped = String::with_capacity(name.len() + 8);
+    for c in name.chars() {
+        if is_unsafe(c) {
+            let mut buf = [0; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                escaped.push_str(&format!("%{b:02X}"));
+            }
+        } else {
+            escaped.push(c);
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Strip the grouping suffix of a PE/COFF section, e.g. `.text$mn` and `.CRT$XCU` become `.text`
+/// and `.CRT`. The linker merges the groups of a section in suffix order, so they are one section
+/// in the binary.
+pub fn merge_pe_section(section: &str) -> &str {
+    match section.split_once('$') {
+        Some((name, _)) if !name.is_empty() => name,
+        _ => section,
+    }
+}
+
+/// Replace the generic arguments of a symbol part with `_`, e.g. `drop_in_place<foo::Bar>`
+/// becomes `drop_in_place<_>` and the turbofish part `<u8>` becomes `<_>`
+/// Nested brackets are erased with their outermost group, names without any are borrowed
+pub fn erase_generics(name: &str) -> Cow<'_, str> {
+    if !name.contains('<') {
+        return Cow::Borrowed(name);
+    }
+    let mut erased = String::with_capacity(name.len());
+    let mut depth = 0usize;
+    for c in name.chars() {
+        match c {
+            '<' => {
+                if depth == 0 {
+                    erased.push_str("<_>");
+                }
+                depth += 1;
+            }
+            '>' if depth > 0 => depth -= 1,
+            _ if depth > 0 => {}
+            c => erased.push(c),
+        }
+    }
+    Cow::Owned(erased)
+}
+
+/// Map a platform specific section name to a canonical one, e.g. `.text`, `__TEXT,__text`
+/// and `.text$mn` all become `text`. Unknown sections are returned unchanged.
+pub fn canonical_section(section: &str) -> &str {
+    // Mach-O `segment,section` and PE `section$group`
+    let name = mach_o_section_name(section).unwrap_or(section);
+    let name = name.split('$').next().unwrap_or(name);
+    CANONICAL_SECTIONS
+        .iter()
+        .find(|(from, _)| *from == name)
+        .map_or(section, |(_, to)| to)
+}
+
+/// Check if a symbol is a bloaty aggregate remainder row like `[1843 Others]`
+#[inline]
+pub fn is_others_symbol(s: &str) -> bool {
+    s.strip_prefix('[')
+        .and_then(|s| s.strip_suffix(" Others]"))
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Check if a symbol is an entry point or startup symbol like `main`, `_start` or
+/// `std::rt::lang_start::<()>`
+pub fn is_entry_symbol(s: &str) -> bool {
+    ENTRY_SYMBOLS.contains(&s)
+        || s.strip_prefix("std::rt::").is_some_and(|rest| {
+            let function = rest.split(['<', ':']).next().unwrap_or(rest);
+            ENTRY_RT_FUNCTIONS.contains(&function)
+        })
+}
+
+/// Check if a symbol is a known compiler builtin or runtime symbol
+#[inline]
+pub fn is_runtime_symbol(s: &str) -> bool {
+    RUNTIME_SYMBOLS.contains(&s) || RUNTIME_PREFIXES.iter().any(|p| s.starts_with(p))
+}
 
 /// Rust primitive types that should be converted to std::primitive::xxx
 const PRIMITIVE_TYPES: &[&str] = &[
@@ -21,10 +263,17 @@ fn is_primitive_type(s: &str) -> bool {
 /// - `&str` -> `std::primitive::str`
 /// - `u8`, `i32`, etc. -> `std::primitive::xxx`
 /// - `[u8]` -> `std::primitive::slice`
+/// - `[alloc::string::String]` -> `alloc::string::String` if `slice_element` is set
 /// - `*mut T` / `*const T` -> keeps the inner type
-fn normalize_type(s: &str) -> String {
+/// - `fn(u32) -> u64`, `unsafe extern "C" fn()` -> `std::primitive::fn`
+fn normalize_type(s: &str, slice_element: bool) -> String {
     let s = s.trim();
 
+    // Handle function pointer types, with optional `unsafe` and `extern "ABI"` qualifiers
+    if strip_fn_qualifiers(s).starts_with("fn(") {
+        return "std::primitive::fn".to_string();
+    }
+
     // Handle unit type ()
     if s == "()" {
         return "std::primitive::unit".to_string();
@@ -36,25 +285,34 @@ fn normalize_type(s: &str) -> String {
     }
 
     // Handle slice types like [u8]
-    if s.starts_with('[') && s.ends_with(']') {
+    if let Some(inner) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        // Slices of a named type, like [alloc::string::String], can use the element type
+        if slice_element {
+            // Arrays [T; N] keep the element type
+            let element = normalize_type(inner.split(';').next().unwrap_or(inner), true);
+            if !element.starts_with("std::primitive::") && element != "_" {
+                return element;
+            }
+        }
         return "std::primitive::slice".to_string();
     }
 
-    // Handle reference types &str, &T
+    // Handle reference types &str, &T, &mut T
     if let Some(inner) = s.strip_prefix('&') {
         let inner = inner.trim();
+        let inner = inner.strip_prefix("mut ").unwrap_or(inner).trim();
         if is_primitive_type(inner) {
             return format!("std::primitive::{}", inner);
         }
-        return normalize_type(inner);
+        return normalize_type(inner, slice_element);
     }
 
     // Handle pointer types *mut T, *const T
     if let Some(inner) = s.strip_prefix("*mut ") {
-        return normalize_type(inner.trim());
+        return normalize_type(inner.trim(), slice_element);
     }
     if let Some(inner) = s.strip_prefix("*const ") {
-        return normalize_type(inner.trim());
+        return normalize_type(inner.trim(), slice_element);
     }
 
     // Handle primitive types
@@ -65,13 +323,45 @@ fn normalize_type(s: &str) -> String {
     s.to_string()
 }
 
+/// Check if a type is a generic parameter placeholder like `T`, `W` or `_` rather than a path
+#[inline]
+fn is_type_placeholder(s: &str) -> bool {
+    s == "_" || (s.len() == 1 && s.chars().all(|c| c.is_ascii_uppercase()))
+}
+
+/// Strip the `unsafe` and `extern "ABI"` qualifiers of a function pointer type
+fn strip_fn_qualifiers(s: &str) -> &str {
+    let s = s.strip_prefix("unsafe ").unwrap_or(s).trim_start();
+    match s.strip_prefix("extern ") {
+        // extern "C" fn(), the ABI string never contains a quote
+        Some(rest) => match rest.trim_start().strip_prefix('"') {
+            Some(abi) => abi.split_once('"').map_or(s, |(_, rest)| rest.trim_start()),
+            None => rest.trim_start(),
+        },
+        None => s,
+    }
+}
+
 /// Check if a symbol string represents a valid crate name
 /// Returns false if the symbol contains invalid patterns or is a special marker
 #[inline]
 pub fn symbol_is_crate(s: &str) -> bool {
     // Reject symbols with invalid patterns: ".." or spaces
     // Reject special markers that start with '['
-    !s.contains("..") && !s.contains(' ') && !s.starts_with('[')
+    // C symbols that happen to contain `::` (e.g. `123abc::x`) don't start with a Rust identifier
+    !s.contains("..")
+        && !s.contains(' ')
+        && !s.starts_with('[')
+        && is_identifier(s.split("::").next().unwrap_or(s))
+}
+
+/// Check if `s` is a Rust identifier, `^[A-Za-z_][A-Za-z0-9_]*$` with non-ASCII letters
+/// allowed like in Rust (e.g. `café`)
+#[inline]
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
 }
 
 /// Extract crate name and symbol parts from a symbol string
@@ -84,9 +374,46 @@ pub fn symbol_is_crate(s: &str) -> bool {
 /// - Double angle: `<<Type as Trait>::method as OtherTrait>::func`
 ///   Returns the crate name and all symbol parts if valid, None otherwise
 pub fn get_crate_name(symbols: &str) -> Option<(String, Vec<String>)> {
+    parse_symbol(symbols, false)
+}
+
+/// Like [`get_crate_name`], honoring the symbol parsing settings in `options`
+pub fn get_crate_name_with(
+    symbols: &str,
+    options: &ConvertOptions,
+) -> Option<(String, Vec<String>)> {
+    let (crate_name, mut parts) = parse_symbol(symbols, options.slice_element_crate)?;
+    if options.strip_disambiguators {
+        for part in &mut parts {
+            let len = strip_disambiguator(part).len();
+            part.truncate(len);
+        }
+    }
+    Some((crate_name, parts))
+}
+
+/// Strip the `#N` disambiguator of a symbol part, e.g. `bar#2` becomes `bar`
+/// Brace forms like `{closure#0}` are kept
+fn strip_disambiguator(part: &str) -> &str {
+    if part.starts_with('{') {
+        return part;
+    }
+    match part.rsplit_once('#') {
+        Some((name, n))
+            if !name.is_empty() && !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            name
+        }
+        _ => part,
+    }
+}
+
+/// Extract crate name and symbol parts, see [`get_crate_name`]
+/// With `slice_element`, slice methods like `<[T]>::concat` are attributed to the crate of `T`
+fn parse_symbol(symbols: &str, slice_element: bool) -> Option<(String, Vec<String>)> {
     // Handle angle bracket symbols (trait impls, type methods)
     if symbols.starts_with('<') {
-        return parse_angle_bracket_symbol(symbols);
+        return parse_angle_bracket_symbol(symbols, slice_element);
     }
 
     // Handle regular symbols (including closures like {closure#0})
@@ -110,14 +437,20 @@ pub fn get_crate_name(symbols: &str) -> Option<(String, Vec<String>)> {
 
 /// Parse angle bracket symbols like trait impls and type methods
 /// Extracts only the innermost type path and the outermost method name
-fn parse_angle_bracket_symbol(symbols: &str) -> Option<(String, Vec<String>)> {
+fn parse_angle_bracket_symbol(symbols: &str, slice_element: bool) -> Option<(String, Vec<String>)> {
     // Extract innermost type and outermost method
-    let (inner_type, outer_method) = extract_inner_type_and_outer_method(symbols)?;
+    let (inner_type, inner_trait, outer_method) = extract_inner_type_and_outer_method(symbols)?;
 
     let mut parts = Vec::with_capacity(4);
 
     // Normalize and add type path parts
-    let normalized_type = normalize_type(&inner_type);
+    // A generic parameter as the type (`<W as core::fmt::Write>`) names no crate, the trait is used
+    let mut normalized_type = normalize_type(&inner_type, slice_element);
+    if is_type_placeholder(&normalized_type) {
+        normalized_type = inner_trait
+            .filter(|t| !is_type_placeholder(t))
+            .map(|t| normalize_type(&t, slice_element))?;
+    }
     for part in split_symbol_parts(&normalized_type) {
         if !part.is_empty() && part != "<>" {
             parts.push(part);
@@ -142,56 +475,66 @@ fn parse_angle_bracket_symbol(symbols: &str) -> Option<(String, Vec<String>)> {
     None
 }
 
-/// Extract the innermost type and outermost method from nested angle bracket expression
-/// For `<<u64 as Trait1>::method1 as Trait2>::method2` returns ("u64", "method2")
-fn extract_inner_type_and_outer_method(s: &str) -> Option<(String, String)> {
-    let s = s.trim();
-
-    if !s.starts_with('<') {
-        return None;
-    }
+/// Maximum number of nested `<...>` type levels unwrapped before a symbol is left unattributed
+const MAX_ANGLE_NESTING: usize = 64;
 
-    // Find matching '>' for the outermost angle bracket
+/// Find the matching '>' for the angle bracket at the start of `s`
+/// The `>` of a `->` return type arrow doesn't close an angle bracket
+fn find_closing_angle(s: &str) -> Option<usize> {
     let mut depth = 0;
-    let mut close_pos = None;
     for (i, c) in s.char_indices() {
         match c {
             '<' => depth += 1,
-            '>' => {
+            '>' if !s[..i].ends_with('-') => {
                 depth -= 1;
                 if depth == 0 {
-                    close_pos = Some(i);
-                    break;
+                    return Some(i);
                 }
             }
             _ => {}
         }
     }
+    None
+}
 
-    let close_pos = close_pos?;
+/// Extract the innermost type, its trait and the outermost method from nested angle bracket expression
+/// For `<<u64 as Trait1>::method1 as Trait2>::method2` returns ("u64", Some("Trait1"), "method2")
+/// Returns None if the type is nested deeper than `MAX_ANGLE_NESTING` levels
+fn extract_inner_type_and_outer_method(s: &str) -> Option<(String, Option<String>, String)> {
+    let mut s = s.trim();
 
-    // Get outer method (after `>::`)
-    let outer_method = s[close_pos..].strip_prefix(">::").unwrap_or("").to_string();
+    if !s.starts_with('<') {
+        return None;
+    }
 
-    // Get inner content
-    let inner = &s[1..close_pos];
+    let close_pos = find_closing_angle(s)?;
 
-    // Find type part (before " as " at depth 0)
-    let type_part = find_type_part(inner);
+    // Get outer method (after `>::`)
+    let outer_method = s[close_pos..].strip_prefix(">::").unwrap_or("").to_string();
 
-    // If type_part starts with '<', recursively extract innermost type
-    if type_part.starts_with('<') {
-        let (inner_type, _) = extract_inner_type_and_outer_method(type_part)?;
-        Some((inner_type, outer_method))
-    } else {
-        Some((type_part.to_string(), outer_method))
+    // Unwrap `<<T as A>::f as B>` one level at a time until the type part is not a `<...>`
+    let mut close_pos = close_pos;
+    for _ in 0..MAX_ANGLE_NESTING {
+        // Find type part (before " as " at depth 0)
+        let inner = &s[1..close_pos];
+        let type_part = find_type_part(inner);
+        if !type_part.starts_with('<') {
+            let trait_part = inner[type_part.len()..]
+                .strip_prefix(" as ")
+                .map(|t| t.trim().to_string());
+            return Some((type_part.to_string(), trait_part, outer_method));
+        }
+        s = type_part.trim();
+        close_pos = find_closing_angle(s)?;
     }
+    None
 }
 
 /// Clean a symbol part to make it a valid identifier
-/// Removes trailing `<>`, `()`, and other invalid characters
+/// Removes surrounding quotes and backticks left by unusual CSV escaping,
+/// trailing `<>`, `()`, and other invalid characters
 fn clean_symbol_part(s: &str) -> String {
-    let mut result = s.to_string();
+    let mut result = s.trim_matches(|c| c == '"' || c == '`').to_string();
 
     // Remove trailing () and <>
     while result.ends_with("()") || result.ends_with("<>") {
@@ -262,17 +605,16 @@ fn split_symbol_parts(s: &str) -> Vec<String> {
 
 /// Find the type part in an angle bracket expression
 /// Handles nested angle brackets like `<u8 as <[_]>::to_vec_in::ConvertVec>`
+/// Only slices at char boundaries, so non-ASCII identifiers are safe
 fn find_type_part(inner: &str) -> &str {
     // Find " as " that is not inside nested angle brackets
     let mut depth = 0;
-    let bytes = inner.as_bytes();
-    let as_pattern = b" as ";
-
-    for i in 0..inner.len() {
-        match bytes[i] {
-            b'<' => depth += 1,
-            b'>' => depth -= 1,
-            b' ' if depth == 0 && i + 4 <= inner.len() && &bytes[i..i + 4] == as_pattern => {
+
+    for (i, c) in inner.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' if !inner[..i].ends_with('-') => depth -= 1,
+            ' ' if depth == 0 && inner[i..].starts_with(" as ") => {
                 return &inner[..i];
             }
             _ => {}
@@ -281,36 +623,376 @@ fn find_type_part(inner: &str) -> &str {
     inner
 }
 
+/// Resolve a single symbol to its full path (package path + symbol parts), without the section
+/// Returns None if no crate can be extracted from the symbol
+///
+/// # Example
+///
+/// ```
+/// use bloaty_metafile::{Packages, resolve_symbol};
+///
+/// let path = resolve_symbol("<url::Url>::set_password", &Packages::default());
+/// assert!(path.is_some());
+/// ```
+pub fn resolve_symbol(symbol: &str, packages: &Packages) -> Option<Vec<String>> {
+    let (crate_name, parts) = get_crate_name(symbol)?;
+    let pkg_path = packages.get_path(&crate_name);
+    let mut path = Vec::with_capacity(pkg_path.len() + parts.len());
+    path.extend_from_slice(pkg_path);
+    if pkg_path.is_empty() {
+        path.push(packages.crate_name(&crate_name).to_string());
+    }
+    path.extend_from_slice(&parts[1..]);
+    Some(path)
+}
+
+/// Check if a symbol part is a closure like `{closure#0}`
+#[inline]
+fn is_closure_part(s: &str) -> bool {
+    s.starts_with("{closure")
+}
+
+/// Check if the symbol is in the `iter` or `ops` module of a standard library crate
+fn is_std_adapter(crate_name: &str, parts: &[String]) -> bool {
+    STD_CRATES.contains(&crate_name)
+        && parts
+            .get(1)
+            .is_some_and(|module| module == "iter" || module == "ops")
+}
+
+/// Find the first crate in the generic arguments of a symbol that is neither
+/// `own_crate` nor a standard library crate, e.g. `my_crate` for
+/// `core::iter::adapters::map::map_fold::<my_crate::Item, ()>::{closure#0}`
+pub fn find_generic_crate(symbols: &str, own_crate: &str) -> Option<String> {
+    let mut depth = 0;
+    let mut token = String::new();
+    for c in symbols.chars().chain(std::iter::once(' ')) {
+        if depth > 0 && (c.is_alphanumeric() || c == '_' || c == ':') {
+            token.push(c);
+            continue;
+        }
+        if let Some((crate_name, _)) = token.split_once("::")
+            && symbol_is_crate(crate_name)
+            && !crate_name.is_empty()
+            && crate_name != own_crate
+            && !STD_CRATES.contains(&crate_name)
+        {
+            return Some(crate_name.to_string());
+        }
+        token.clear();
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Find the feature of the longest module path prefix in `feature_map` matching the symbol parts
+/// Prefixes are `::` separated module paths like `tokio::net`
+fn find_feature<'a>(feature_map: &'a HashMap<String, String>, parts: &[String]) -> Option<&'a str> {
+    feature_map
+        .iter()
+        .filter_map(|(prefix, feature)| {
+            module_prefix_len(prefix, parts).map(|len| (len, feature.as_str()))
+        })
+        .max()
+        .map(|(_, feature)| feature)
+}
+
+/// Find the crate of the longest C symbol pattern in `c_symbols` matching the symbol
+/// Patterns ending in `*` match symbols starting with the rest, others match exactly
+fn find_c_symbol_crate(c_symbols: &HashMap<String, String>, symbol: &str) -> Option<String> {
+    c_symbols
+        .iter()
+        .filter(|(pattern, _)| match pattern.strip_suffix('*') {
+            Some(prefix) => symbol.starts_with(prefix),
+            None => symbol == pattern.as_str(),
+        })
+        // An exact pattern wins over a prefix of the same length
+        .max_by_key(|(pattern, _)| (pattern.trim_end_matches('*').len(), !pattern.ends_with('*')))
+        .map(|(_, crate_name)| crate_name.replace('-', "_"))
+}
+
+/// Find the component of the longest crate name prefix in `components` matching the crate
+/// Prefixes are crate names or their beginning like `tokio` (matching `tokio_util` too)
+fn find_component<'a>(
+    components: &'a HashMap<String, String>,
+    crate_name: &str,
+) -> Option<&'a str> {
+    components
+        .iter()
+        .filter(|(prefix, _)| crate_name.starts_with(&prefix.replace('-', "_")))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, component)| component.as_str())
+}
+
+/// Number of parts of a `::` separated module path prefix like `tokio::net`, if the symbol
+/// parts start with it
+fn module_prefix_len(prefix: &str, parts: &[String]) -> Option<usize> {
+    let prefix: Vec<_> = prefix.split("::").collect();
+    let matched = prefix.len() <= parts.len()
+        && prefix
+            .iter()
+            .zip(parts)
+            .all(|(p, part)| p.replace('-', "_") == *part);
+    matched.then_some(prefix.len())
+}
+
+/// Append the section level and the symbol parts to a path
+/// With `sections_as_leaves` the path stops at the section, with `section_suffix` the section is
+/// appended to the leaf name instead (`clone/structured_clone [.text]`)
+fn push_section_and_symbol(
+    path: &mut Vec<String>,
+    section: Option<String>,
+    parts: impl IntoIterator<Item = String>,
+    options: &ConvertOptions,
+) {
+    if options.sections_as_leaves {
+        path.extend(section);
+        return;
+    }
+    if options.section_suffix {
+        let len = path.len();
+        path.extend(parts);
+        match (section, path.len() > len) {
+            (Some(section), true) => {
+                let leaf = path.last_mut().expect("path has a leaf");
+                leaf.push_str(" [");
+                leaf.push_str(&section);
+                leaf.push(']');
+            }
+            // No symbol parts to suffix, keep the section as a level
+            (section, _) => path.extend(section),
+        }
+        return;
+    }
+    path.extend(section);
+    path.extend(parts);
+}
+
 /// Build a hierarchical path from a symbol record
 /// Combines package dependencies, sections, and symbol parts into a single path
-pub fn get_path_from_record(symbols: String, sections: String, packages: &Packages) -> Vec<String> {
-    match get_crate_name(&symbols) {
+/// The crate is always taken from the symbol itself, never from the section or compile unit
+/// the code was emitted in, so inlined or monomorphized `hashbrown::...` code stays under
+/// `hashbrown` (unless `blame_closures` re-roots a closure)
+/// Also returns the length of the path prefix that ends with the crate (or the bucket like
+/// `SECTIONS`) the symbol is attributed to
+/// Extra dimensions nest above the symbol's path together with the section:
+/// .text,foo.rs,foo::a -> .text/foo.rs/foo/a
+pub fn get_path_and_crate(
+    symbols: String,
+    sections: String,
+    dimensions: &[String],
+    packages: &Packages,
+    options: &ConvertOptions,
+) -> (Vec<String>, usize) {
+    // bloaty's remainder of a section can have the section as its symbol: .text,.text
+    let symbols = if symbols == sections {
+        UNATTRIBUTED_NAME.to_string()
+    } else {
+        symbols
+    };
+    let sections = if options.merge_pe_sections {
+        merge_pe_section(&sections).to_string()
+    } else {
+        sections
+    };
+    let sections = if options.section_canonical {
+        canonical_section(&sections).to_string()
+    } else {
+        sections
+    };
+    // An empty section would give an empty node name (`foo//bar`), use a placeholder or leave
+    // the level out
+    let sections = if !sections.is_empty() {
+        Some(sanitize_node_name(&sections).into_owned())
+    } else if options.drop_empty_sections {
+        None
+    } else {
+        Some(NO_SECTION_NAME.to_string())
+    };
+    if !dimensions.is_empty() {
+        let mut path: Vec<String> = sections
+            .into_iter()
+            .chain(
+                dimensions
+                    .iter()
+                    .filter(|d| !d.is_empty())
+                    .map(|d| sanitize_node_name(d).into_owned()),
+            )
+            .collect();
+        let prefix_len = path.len();
+        let (symbol_path, crate_len) =
+            get_symbol_path(symbols, None, dimensions, packages, options);
+        path.extend(symbol_path);
+        return (path, prefix_len + crate_len);
+    }
+    get_symbol_path(symbols, sections, &[], packages, options)
+}
+
+/// The path of a symbol below an optional section level, see [`get_path_and_crate`]
+/// `dimensions` pick the major version of crates split by `group_by_version_major`
+fn get_symbol_path(
+    symbols: String,
+    sections: Option<String>,
+    dimensions: &[String],
+    packages: &Packages,
+    options: &ConvertOptions,
+) -> (Vec<String>, usize) {
+    if options.entry_bucket && is_entry_symbol(&symbols) {
+        // Entry point and startup code: ENTRY/section/symbol
+        let mut path = vec![ENTRY_NAME.to_string()];
+        push_section_and_symbol(&mut path, sections, [symbols], options);
+        return (path, 1);
+    }
+    // C symbols of a `-sys` crate's native library: openssl_ssl_new -> openssl_sys/.text/openssl_ssl_new
+    let parsed = get_crate_name_with(&symbols, options).or_else(|| {
+        find_c_symbol_crate(&options.c_symbols, &symbols)
+            .map(|crate_name| (crate_name.clone(), vec![crate_name, symbols.clone()]))
+    });
+    match parsed {
+        None if is_others_symbol(&symbols) => {
+            // Already aggregated remainder from bloaty: OTHERS/section/[N Others]
+            let mut path = vec![OTHERS_NAME.to_string()];
+            push_section_and_symbol(&mut path, sections, [symbols], options);
+            (path, 1)
+        }
+        None if is_runtime_symbol(&symbols) => {
+            // Compiler builtins and runtime symbols: RUNTIME/section/symbol
+            let mut path = vec![RUNTIME_NAME.to_string()];
+            push_section_and_symbol(&mut path, sections, [symbols], options);
+            (path, 1)
+        }
         None => {
             // No crate found: build path from sections
             // Pre-allocate capacity for sections + symbol parts
             let symbol_parts_count = symbols.matches("::").count() + 1;
             let mut path = Vec::with_capacity(2 + symbol_parts_count);
             path.push(SECTIONS_NAME.to_string());
-            path.push(sections);
-            path.extend(symbols.split("::").map(String::from));
-            path
+            push_section_and_symbol(
+                &mut path,
+                sections,
+                symbols.split("::").map(String::from),
+                options,
+            );
+            (path, 1)
         }
         Some((crate_name, symbols_parts)) => {
+            // Closures in another crate's generic code are attributed to the crate in the
+            // generic arguments, keeping the full symbol below it:
+            // core::iter::map_fold::<my::Item>::{closure#0} -> my/.text/core/iter/map_fold/...
+            // Iterator adapters and operator impls of the standard library likewise, their
+            // size is driven by the user types and closures they are instantiated with:
+            // core::iter::adapters::map::Map<I, my::F> -> my/.text/core/iter/...
+            let blamed = if (options.blame_closures
+                && symbols_parts.iter().any(|p| is_closure_part(p)))
+                || (options.collapse_std_generics && is_std_adapter(&crate_name, &symbols_parts))
+            {
+                find_generic_crate(&symbols, &crate_name)
+            } else {
+                None
+            };
+            let (crate_name, symbols_parts) = match blamed {
+                Some(user_crate) => {
+                    let mut parts = Vec::with_capacity(symbols_parts.len() + 1);
+                    parts.push(user_crate.clone());
+                    parts.extend(symbols_parts);
+                    (user_crate, parts)
+                }
+                None => (crate_name, symbols_parts),
+            };
+
+            // Async runtime and threading code: RUNTIME/tokio/.text/runtime/task/...
+            if options
+                .runtime_prefixes
+                .iter()
+                .any(|prefix| module_prefix_len(prefix, &symbols_parts).is_some())
+            {
+                let mut path = vec![RUNTIME_NAME.to_string(), crate_name];
+                push_section_and_symbol(
+                    &mut path,
+                    sections,
+                    symbols_parts.into_iter().skip(1),
+                    options,
+                );
+                return (path, 1);
+            }
+
+            // Proc-macro crates aren't linked into the binary, symbols naming them come from
+            // generated code: [proc-macro]/serde_derive/.text/...
+            if options
+                .proc_macros
+                .iter()
+                .any(|p| p.replace('-', "_") == crate_name)
+            {
+                let mut path = vec![PROC_MACRO_NAME.to_string(), crate_name];
+                push_section_and_symbol(
+                    &mut path,
+                    sections,
+                    symbols_parts.into_iter().skip(1),
+                    options,
+                );
+                return (path, 2);
+            }
+
             // Build path: crate dependency path + section + symbol parts
             // Example: .text,llrt_utils::clone::structured_clone -> llrt/llrt_utils/.text/clone/structured_clone
-            let pkg_path = packages.get_path(&crate_name);
-            let mut path = Vec::with_capacity(pkg_path.len() + 1 + symbols_parts.len() - 1);
+            let pkg_path = packages.get_path_in(&crate_name, dimensions);
+            let mut path = Vec::with_capacity(pkg_path.len() + 3 + symbols_parts.len());
+            // Component level from the component map: COMPONENT:networking/app/hyper/.text/...
+            if let Some(component) = find_component(&options.components, &crate_name) {
+                path.push(format!("{COMPONENT_PREFIX}{component}"));
+            }
             path.extend_from_slice(pkg_path);
-            path.push(sections);
-            path.extend_from_slice(&symbols_parts[1..]);
-            path
+            // Crates without a dependency path (no lockfile) are top-level nodes
+            if pkg_path.is_empty() {
+                path.push(packages.crate_name(&crate_name).to_string());
+            }
+            let crate_len = path.len();
+            // Feature level from the feature map: llrt/llrt_utils/FEATURE:clone/.text/clone/structured_clone
+            if let Some(feature) = find_feature(&options.feature_map, &symbols_parts) {
+                path.push(format!("{FEATURE_PREFIX}{feature}"));
+            }
+            // Attributed symbols can skip the section level: llrt/llrt_utils/clone/structured_clone
+            let sections = sections.filter(|_| !options.flatten_sections);
+            push_section_and_symbol(
+                &mut path,
+                sections,
+                symbols_parts.into_iter().skip(1),
+                options,
+            );
+            (path, crate_len)
         }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{get_crate_name, symbol_is_crate};
+    use super::{
+        ENTRY_NAME, OTHERS_NAME, PROC_MACRO_NAME, RUNTIME_NAME, SECTIONS_NAME, UNATTRIBUTED_NAME,
+        canonical_section, clean_symbol_part, erase_generics, find_generic_crate, get_crate_name,
+        get_crate_name_with, get_path_and_crate, is_entry_symbol, is_others_symbol,
+        merge_pe_section, sanitize_node_name, section_kind, strip_disambiguator, symbol_is_crate,
+    };
+    use crate::{
+        options::ConvertOptions,
+        packages::{
+            Packages,
+            test::{dependency_tree, records},
+        },
+    };
+
+    /// The path of a record, without the crate prefix length
+    fn get_path_from_record(
+        symbols: String,
+        sections: String,
+        packages: &Packages,
+        options: &ConvertOptions,
+    ) -> Vec<String> {
+        get_path_and_crate(symbols, sections, &[], packages, options).0
+    }
 
     #[test]
     fn test_symbol_is_crate() {
@@ -324,6 +1006,11 @@ mod test {
             ("another::valid::crate", true),
             ("invalid crate", false),
             ("..invalid", false),
+            ("foo_bar::baz", true),
+            ("_private::x", true),
+            ("123abc::x", false),
+            ("foo-bar::x", false),
+            ("", false),
         ];
 
         for (symbol, expected) in test_cases.iter() {
@@ -336,6 +1023,737 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_runtime_symbols() {
+        let packages = Packages::default();
+        for symbol in ["__rust_alloc", "memcpy", "__udivdi3", "rust_eh_personality"] {
+            let path = get_path_from_record(
+                symbol.to_string(),
+                ".text".to_string(),
+                &packages,
+                &ConvertOptions::default(),
+            );
+            assert_eq!(path, vec![RUNTIME_NAME, ".text", symbol]);
+        }
+
+        let path = get_path_from_record(
+            "my_func".to_string(),
+            ".text".to_string(),
+            &packages,
+            &ConvertOptions::default(),
+        );
+        assert_ne!(path[0], RUNTIME_NAME);
+    }
+
+    #[test]
+    fn test_entry_symbols() {
+        let options = ConvertOptions {
+            entry_bucket: true,
+            ..Default::default()
+        };
+        let path = |symbol: &str, options: &ConvertOptions| {
+            get_path_from_record(
+                symbol.to_string(),
+                ".text".to_string(),
+                &Packages::default(),
+                options,
+            )
+        };
+        for symbol in [
+            "main",
+            "_start",
+            "__libc_start_main",
+            "rust_begin_unwind",
+            "std::rt::lang_start_internal",
+            "std::rt::lang_start::<()>::{{closure}}",
+        ] {
+            assert_eq!(path(symbol, &options), vec![ENTRY_NAME, ".text", symbol]);
+        }
+        assert!(!is_entry_symbol("my_app::main"));
+        assert!(!is_entry_symbol("domain"));
+        assert!(!is_entry_symbol("mycrate::lang_start_helper"));
+        assert!(!is_entry_symbol("mycrate::rt::lang_start"));
+        assert!(!is_entry_symbol("std::rt::lang_start_helper"));
+        assert!(is_entry_symbol("std::rt::lang_start<()>"));
+
+        // Without the option main stays unattributed and lang_start in std
+        let options = ConvertOptions::default();
+        assert_eq!(path("main", &options), vec![SECTIONS_NAME, ".text", "main"]);
+        assert_eq!(path("std::rt::lang_start_internal", &options)[0], "std");
+    }
+
+    #[test]
+    fn test_erase_generics() {
+        assert_eq!(erase_generics("write"), "write");
+        assert_eq!(erase_generics("<u8>"), "<_>");
+        assert_eq!(
+            erase_generics("drop_in_place<foo::Bar<u8>>"),
+            "drop_in_place<_>"
+        );
+        assert_eq!(erase_generics("from<A><B>"), "from<_><_>");
+    }
+
+    #[test]
+    fn test_runtime_prefixes() {
+        let options = ConvertOptions {
+            runtime_prefixes: vec!["tokio::runtime".to_string(), "std::thread".to_string()],
+            ..Default::default()
+        };
+        let path = |symbol: &str| {
+            get_path_and_crate(
+                symbol.to_string(),
+                ".text".to_string(),
+                &[],
+                &Packages::default(),
+                &options,
+            )
+        };
+        let (tokio, crate_len) = path("tokio::runtime::task::raw::poll");
+        assert_eq!(
+            tokio,
+            vec![
+                RUNTIME_NAME,
+                "tokio",
+                ".text",
+                "runtime",
+                "task",
+                "raw",
+                "poll"
+            ]
+        );
+        assert_eq!(crate_len, 1);
+        assert_eq!(path("std::thread::spawn").0[..2], [RUNTIME_NAME, "std"]);
+        // Only whole module names match
+        assert_eq!(path("tokio::net::TcpStream::connect").0[0], "tokio");
+        assert_eq!(path("std::threads::f").0[0], "std");
+    }
+
+    #[test]
+    fn test_proc_macro() {
+        let options = ConvertOptions {
+            proc_macros: vec!["serde-derive".to_string()],
+            ..Default::default()
+        };
+        let path = |symbol: &str| {
+            get_path_and_crate(
+                symbol.to_string(),
+                ".text".to_string(),
+                &[],
+                &Packages::default(),
+                &options,
+            )
+        };
+        assert_eq!(
+            path("serde_derive::de::expand"),
+            (
+                vec![
+                    PROC_MACRO_NAME.to_string(),
+                    "serde_derive".to_string(),
+                    ".text".to_string(),
+                    "de".to_string(),
+                    "expand".to_string()
+                ],
+                2
+            )
+        );
+        assert_eq!(path("serde::de::f").0[0], "serde");
+    }
+
+    #[test]
+    fn test_section_as_symbol() {
+        let path = |symbol: &str| {
+            get_path_from_record(
+                symbol.to_string(),
+                ".text".to_string(),
+                &Packages::default(),
+                &ConvertOptions::default(),
+            )
+        };
+        assert_eq!(
+            path(".text"),
+            vec![SECTIONS_NAME, ".text", UNATTRIBUTED_NAME]
+        );
+        assert_eq!(path(".rodata"), vec![SECTIONS_NAME, ".text", ".rodata"]);
+    }
+
+    #[test]
+    fn test_custom_section_name() {
+        assert_eq!(sanitize_node_name(".text"), ".text");
+        assert_eq!(sanitize_node_name("my data/v1"), "my%20data%2Fv1");
+        assert_eq!(sanitize_node_name("50%\t"), "50%25%09");
+
+        // Only `__SEG,__sect` is split
+        assert_eq!(canonical_section("foo,__text"), "foo,__text");
+        assert_eq!(canonical_section("__TEXT,__text"), "text");
+        assert_eq!(section_kind("foo,__const"), None);
+
+        let path = get_path_from_record(
+            "foo::bar".to_string(),
+            "my data,v1/x".to_string(),
+            &Packages::default(),
+            &ConvertOptions {
+                section_canonical: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(path, vec!["foo", "my%20data,v1%2Fx", "bar"]);
+    }
+
+    #[test]
+    fn test_dimension_with_slash() {
+        let dimensions = ["/rustc/abc123/library/core/src/lib.rs".to_string()];
+        let (path, crate_len) = get_path_and_crate(
+            "core::fmt::write".to_string(),
+            ".text".to_string(),
+            &dimensions,
+            &Packages::default(),
+            &ConvertOptions::default(),
+        );
+        assert_eq!(
+            path,
+            vec![
+                ".text",
+                "%2Frustc%2Fabc123%2Flibrary%2Fcore%2Fsrc%2Flib.rs",
+                "core",
+                "fmt",
+                "write"
+            ]
+        );
+        assert_eq!(crate_len, 3);
+        assert!(
+            path.iter()
+                .all(|part| !part.is_empty() && !part.contains('/'))
+        );
+    }
+
+    #[test]
+    fn test_strip_disambiguators() {
+        assert_eq!(strip_disambiguator("bar#2"), "bar");
+        assert_eq!(strip_disambiguator("{closure#0}"), "{closure#0}");
+        assert_eq!(strip_disambiguator("#2"), "#2");
+        assert_eq!(strip_disambiguator("bar#x"), "bar#x");
+
+        let path = |symbol: &str, strip_disambiguators| {
+            get_path_from_record(
+                symbol.to_string(),
+                ".text".to_string(),
+                &Packages::default(),
+                &ConvertOptions {
+                    strip_disambiguators,
+                    flatten_sections: true,
+                    ..Default::default()
+                },
+            )
+        };
+        assert_eq!(path("foo::bar#2::baz", true), vec!["foo", "bar", "baz"]);
+        assert_eq!(path("foo::bar#3::baz", true), vec!["foo", "bar", "baz"]);
+        assert_eq!(
+            path("foo::bar#2::{closure#1}", true),
+            vec!["foo", "bar", "{closure#1}"]
+        );
+        assert_eq!(path("foo::bar#2::baz", false), vec!["foo", "bar#2", "baz"]);
+    }
+
+    #[test]
+    fn test_non_identifier_crate() {
+        let path = |symbol: &str| {
+            get_path_from_record(
+                symbol.to_string(),
+                ".text".to_string(),
+                &Packages::default(),
+                &ConvertOptions::default(),
+            )
+        };
+        assert_eq!(path("foo_bar::baz"), vec!["foo_bar", ".text", "baz"]);
+        // A C symbol that happens to contain `::` stays unattributed
+        assert_eq!(
+            path("123abc::x"),
+            vec![SECTIONS_NAME, ".text", "123abc", "x"]
+        );
+        assert_eq!(get_crate_name("1st::x"), None);
+    }
+
+    #[test]
+    fn test_others_symbols() {
+        assert!(is_others_symbol("[1843 Others]"));
+        assert!(!is_others_symbol("[section .text]"));
+        assert!(!is_others_symbol("[ Others]"));
+
+        let path = get_path_from_record(
+            "[1843 Others]".to_string(),
+            ".text".to_string(),
+            &Packages::default(),
+            &ConvertOptions::default(),
+        );
+        assert_eq!(path, vec![OTHERS_NAME, ".text", "[1843 Others]"]);
+    }
+
+    #[test]
+    fn test_blame_closures() {
+        let symbol = "core::iter::adapters::map::map_fold::<my_crate::Item, ()>::{closure#0}";
+        assert_eq!(
+            find_generic_crate(symbol, "core"),
+            Some("my_crate".to_string())
+        );
+        assert_eq!(find_generic_crate("core::iter::sum::<u8>", "core"), None);
+
+        let tree = dependency_tree(&[("my_crate", "0.1.0", &[])]);
+        let packages = Packages::new(
+            &tree,
+            &records(&["my_crate::main", symbol]),
+            &ConvertOptions::default(),
+        );
+        let path = |symbol: &str, blame_closures: bool| {
+            let options = ConvertOptions {
+                blame_closures,
+                ..Default::default()
+            };
+            get_path_from_record(symbol.to_string(), ".text".to_string(), &packages, &options)
+        };
+
+        // Without the option the closure stays under core
+        assert_eq!(path(symbol, false)[0], "core");
+        assert_eq!(
+            path(symbol, true),
+            vec![
+                "my_crate",
+                ".text",
+                "core",
+                "iter",
+                "adapters",
+                "map",
+                "map_fold",
+                "<my_crate::Item, ()>",
+                "{closure#0}"
+            ]
+        );
+        // Only closures are affected
+        let symbol = "core::iter::adapters::map::map_fold::<my_crate::Item, ()>";
+        assert_eq!(path(symbol, true)[0], "core");
+    }
+
+    #[test]
+    fn test_collapse_std_generics() {
+        let symbol = "<core::iter::adapters::map::Map<alloc::vec::into_iter::IntoIter<u8>, my_crate::parse::{closure#0}> as core::iter::traits::iterator::Iterator>::next";
+        let tree = dependency_tree(&[("my_crate", "0.1.0", &[])]);
+        let packages = Packages::new(
+            &tree,
+            &records(&["my_crate::main", symbol]),
+            &ConvertOptions::default(),
+        );
+        let path = |symbol: &str, collapse_std_generics: bool| {
+            let options = ConvertOptions {
+                collapse_std_generics,
+                ..Default::default()
+            };
+            get_path_from_record(symbol.to_string(), ".text".to_string(), &packages, &options)
+        };
+
+        assert_eq!(path(symbol, false)[0], "core");
+        assert_eq!(path(symbol, true)[..3], ["my_crate", ".text", "core"]);
+        // Operator impls too
+        let symbol = "core::ops::function::FnOnce::call_once::<my_crate::State, ()>";
+        assert_eq!(path(symbol, true)[..3], ["my_crate", ".text", "core"]);
+        // Without a user crate in the generics the symbol stays under core
+        let symbol = "core::iter::adapters::map::Map<alloc::vec::into_iter::IntoIter<u8>, u8>";
+        assert_eq!(path(symbol, true)[0], "core");
+        // Other std modules are not affected
+        let symbol = "core::fmt::write::<my_crate::State>";
+        assert_eq!(path(symbol, true)[0], "core");
+    }
+
+    #[test]
+    fn test_feature_map() {
+        let options = ConvertOptions {
+            feature_map: [
+                ("tokio::net".to_string(), "net".to_string()),
+                ("tokio::net::tcp".to_string(), "tcp".to_string()),
+                ("tokio::fs".to_string(), "fs".to_string()),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        let packages = Packages::default();
+        let path = |symbol: &str| {
+            get_path_from_record(symbol.to_string(), ".text".to_string(), &packages, &options)
+        };
+
+        assert_eq!(
+            path("tokio::net::udp::send"),
+            vec!["tokio", "FEATURE:net", ".text", "net", "udp", "send"]
+        );
+        assert_eq!(
+            path("tokio::net::tcp::connect"),
+            vec!["tokio", "FEATURE:tcp", ".text", "net", "tcp", "connect"]
+        );
+        assert_eq!(
+            path("tokio::network::x"),
+            vec!["tokio", ".text", "network", "x"]
+        );
+    }
+
+    #[test]
+    fn test_degenerate_symbols() {
+        for symbol in [
+            "", "::", ":::", "a::", "::a", "<", ">", "<>", "<<>>", "<a as>", " ",
+        ] {
+            for section in ["", ".text"] {
+                for options in [
+                    ConvertOptions::default(),
+                    ConvertOptions {
+                        flatten_sections: true,
+                        drop_empty_sections: true,
+                        ..Default::default()
+                    },
+                ] {
+                    let (path, crate_len) = get_path_and_crate(
+                        symbol.to_string(),
+                        section.to_string(),
+                        &[],
+                        &Packages::default(),
+                        &options,
+                    );
+                    assert!(
+                        (1..=path.len()).contains(&crate_len),
+                        "{symbol:?} {section:?} -> {path:?} {crate_len}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_c_symbols() {
+        let options = ConvertOptions {
+            c_symbols: [
+                ("openssl_*".to_string(), "openssl-sys".to_string()),
+                ("SSL_*".to_string(), "openssl-sys".to_string()),
+                ("sqlite3_*".to_string(), "libsqlite3-sys".to_string()),
+                ("sqlite3_openssl".to_string(), "openssl-sys".to_string()),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        let path = |symbol: &str| {
+            get_path_from_record(
+                symbol.to_string(),
+                ".text".to_string(),
+                &Packages::default(),
+                &options,
+            )
+        };
+        assert_eq!(
+            path("openssl_ssl_new"),
+            vec!["openssl_sys", ".text", "openssl_ssl_new"]
+        );
+        assert_eq!(path("SSL_read"), vec!["openssl_sys", ".text", "SSL_read"]);
+        assert_eq!(
+            path("sqlite3_open"),
+            vec!["libsqlite3_sys", ".text", "sqlite3_open"]
+        );
+        assert_eq!(
+            path("sqlite3_openssl"),
+            vec!["openssl_sys", ".text", "sqlite3_openssl"]
+        );
+        // Other C symbols and Rust symbols are not affected
+        assert_eq!(path("deflate")[0], SECTIONS_NAME);
+        assert_eq!(path("openssl::ssl::new")[0], "openssl");
+    }
+
+    #[test]
+    fn test_section_kind() {
+        assert_eq!(section_kind(".text"), Some("code"));
+        assert_eq!(section_kind("__TEXT,__text"), Some("code"));
+        assert_eq!(section_kind(".text$mn"), Some("code"));
+        assert_eq!(section_kind(".rodata"), Some("rodata"));
+        assert_eq!(section_kind("__TEXT,__const"), Some("rodata"));
+        assert_eq!(section_kind(".data"), Some("data"));
+        assert_eq!(section_kind(".bss"), Some("bss"));
+        assert_eq!(section_kind(".eh_frame"), Some("unwind"));
+        assert_eq!(section_kind("__TEXT,__unwind_info"), Some("unwind"));
+        assert_eq!(section_kind(".debug_info"), None);
+    }
+
+    #[test]
+    fn test_components() {
+        let options = ConvertOptions {
+            components: [
+                ("hyper".to_string(), "networking".to_string()),
+                ("reqwest".to_string(), "networking".to_string()),
+                ("egui".to_string(), "ui".to_string()),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        let path = |symbol: &str| {
+            get_path_from_record(
+                symbol.to_string(),
+                ".text".to_string(),
+                &Packages::default(),
+                &options,
+            )
+        };
+        assert_eq!(
+            path("hyper::client::send"),
+            vec!["COMPONENT:networking", "hyper", ".text", "client", "send"]
+        );
+        assert_eq!(
+            path("reqwest::get"),
+            vec!["COMPONENT:networking", "reqwest", ".text", "get"]
+        );
+        // Crate name prefixes match related crates
+        assert_eq!(
+            path("hyper_util::rt::io"),
+            vec!["COMPONENT:networking", "hyper_util", ".text", "rt", "io"]
+        );
+        assert_eq!(path("serde::de::x"), vec!["serde", ".text", "de", "x"]);
+    }
+
+    #[test]
+    fn test_section_suffix() {
+        let options = ConvertOptions {
+            section_suffix: true,
+            ..Default::default()
+        };
+        let path = |symbol: &str, section: &str| {
+            get_path_from_record(
+                symbol.to_string(),
+                section.to_string(),
+                &Packages::default(),
+                &options,
+            )
+        };
+        assert_eq!(
+            path("llrt_utils::clone::structured_clone", ".text"),
+            vec!["llrt_utils", "clone", "structured_clone [.text]"]
+        );
+        assert_eq!(path("foo::DATA", ".rodata"), vec!["foo", "DATA [.rodata]"]);
+        assert_eq!(path("memcpy", ".text"), vec!["RUNTIME", "memcpy [.text]"]);
+        assert_eq!(path("a.b", ".data"), vec!["SECTIONS", "a.b [.data]"]);
+    }
+
+    #[test]
+    fn test_empty_sections() {
+        let path = |symbol: &str, options: &ConvertOptions| {
+            get_path_from_record(
+                symbol.to_string(),
+                String::new(),
+                &Packages::default(),
+                options,
+            )
+        };
+        let options = ConvertOptions::default();
+        assert_eq!(
+            path("foo::bar", &options),
+            vec!["foo", "[no-section]", "bar"]
+        );
+        assert_eq!(
+            path("memcpy", &options),
+            vec!["RUNTIME", "[no-section]", "memcpy"]
+        );
+
+        let options = ConvertOptions {
+            drop_empty_sections: true,
+            ..Default::default()
+        };
+        assert_eq!(path("foo::bar", &options), vec!["foo", "bar"]);
+        assert_eq!(path("memcpy", &options), vec!["RUNTIME", "memcpy"]);
+        assert_eq!(path("a.b", &options), vec!["SECTIONS", "a.b"]);
+    }
+
+    #[test]
+    fn test_merge_pe_section() {
+        assert_eq!(merge_pe_section(".text$mn"), ".text");
+        assert_eq!(merge_pe_section(".text$x"), ".text");
+        assert_eq!(merge_pe_section(".rdata$r"), ".rdata");
+        assert_eq!(merge_pe_section(".CRT$XCU"), ".CRT");
+        assert_eq!(merge_pe_section(".text"), ".text");
+        assert_eq!(merge_pe_section("$weird"), "$weird");
+    }
+
+    #[test]
+    fn test_canonical_section() {
+        // ELF
+        assert_eq!(canonical_section(".rodata"), "rodata");
+        // Mach-O
+        assert_eq!(canonical_section("__TEXT,__text"), "text");
+        assert_eq!(canonical_section("__DATA_CONST,__const"), "rodata");
+        // PE
+        assert_eq!(canonical_section(".text$mn"), "text");
+        assert_eq!(canonical_section(".rdata"), "rodata");
+
+        assert_eq!(canonical_section(".init_array"), ".init_array");
+
+        let options = ConvertOptions {
+            section_canonical: true,
+            ..Default::default()
+        };
+        let path = get_path_from_record(
+            "foo::bar".to_string(),
+            "__TEXT,__text".to_string(),
+            &Packages::default(),
+            &options,
+        );
+        assert!(path.contains(&"text".to_string()), "{path:?}");
+    }
+
+    #[test]
+    fn test_deeply_nested_angle_brackets() {
+        let nested = |n: usize| format!("{}foo::Bar{}", "<".repeat(n), " as T>::m".repeat(n));
+
+        let (crate_name, parts) = get_crate_name(&nested(32)).expect("crate name");
+        assert_eq!(crate_name, "foo");
+        assert_eq!(parts, vec!["foo", "Bar", "m"]);
+
+        // Too deep to unwrap: left unattributed instead of overflowing the stack
+        assert_eq!(get_crate_name(&nested(10_000)), None);
+        let path = get_path_from_record(
+            nested(10_000),
+            ".text".to_string(),
+            &Packages::default(),
+            &ConvertOptions::default(),
+        );
+        assert_eq!(path[0], SECTIONS_NAME);
+    }
+
+    #[test]
+    fn test_slice_element_crate() {
+        let symbol = "<[alloc::string::String]>::concat";
+        let (crate_name, _) = get_crate_name(symbol).expect("crate name");
+        assert_eq!(crate_name, "std");
+
+        let options = ConvertOptions {
+            slice_element_crate: true,
+            ..Default::default()
+        };
+        let (crate_name, parts) = get_crate_name_with(symbol, &options).expect("crate name");
+        assert_eq!(crate_name, "alloc");
+        assert_eq!(parts, vec!["alloc", "string", "String", "concat"]);
+
+        // Arrays and references to slices use the element type too
+        let (crate_name, _) =
+            get_crate_name_with("<&[foo::Bar; 4] as core::fmt::Debug>::fmt", &options)
+                .expect("crate name");
+        assert_eq!(crate_name, "foo");
+
+        // Primitive elements stay a slice
+        let (_, parts) = get_crate_name_with("<[u8]>::to_vec", &options).expect("crate name");
+        assert_eq!(parts, vec!["std", "primitive", "slice", "to_vec"]);
+    }
+
+    #[test]
+    fn test_symbol_crate_wins_over_compile_unit() {
+        let tree = dependency_tree(&[
+            ("app", "0.1.0", &["hashbrown"]),
+            ("hashbrown", "0.15.0", &[]),
+        ]);
+        let symbols = [
+            "hashbrown::raw::RawTable<app::Item>::insert",
+            "<hashbrown::map::HashMap<app::K, app::V>>::get",
+        ];
+        let packages = Packages::new(&tree, &records(&symbols), &ConvertOptions::default());
+
+        // Code monomorphized and emitted in the app's compile unit
+        for symbol in symbols {
+            let path = get_path_from_record(
+                symbol.to_string(),
+                "app.7f3a2b-cgu.03".to_string(),
+                &packages,
+                &ConvertOptions::default(),
+            );
+            assert_eq!(
+                path[..3],
+                ["app", "hashbrown", "app.7f3a2b-cgu.03"],
+                "{symbol}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_fn_pointer_types() {
+        for symbol in [
+            "<fn() as core::fmt::Debug>::fmt",
+            "<unsafe extern \"C\" fn() as core::fmt::Debug>::fmt",
+            "<extern \"system\" fn(u32) as core::fmt::Pointer>::fmt",
+            "<fn(u32) -> u64 as core::fmt::Debug>::fmt",
+            "<unsafe fn(*mut u8) -> core::option::Option<u8> as core::fmt::Debug>::fmt",
+        ] {
+            let (crate_name, parts) = get_crate_name(symbol).expect("crate name");
+            assert_eq!(crate_name, "std", "{symbol}");
+            assert_eq!(parts, vec!["std", "primitive", "fn", "fmt"], "{symbol}");
+        }
+    }
+
+    #[test]
+    fn test_clean_quoted_parts() {
+        assert_eq!(clean_symbol_part("`foo`"), "foo");
+        assert_eq!(clean_symbol_part("\"foo\""), "foo");
+        assert_eq!(clean_symbol_part("\"foo"), "foo");
+        assert_eq!(clean_symbol_part("`new()`"), "new");
+        assert_eq!(clean_symbol_part("'static"), "'static");
+
+        let (crate_name, parts) = get_crate_name("`foo`::\"bar\"::baz").expect("crate name");
+        assert_eq!(crate_name, "foo");
+        assert_eq!(parts, vec!["foo", "bar", "baz"]);
+    }
+
+    #[test]
+    fn test_unicode_symbols() {
+        let result = get_crate_name("<café::Größe as core::fmt::Debug>::fmt");
+        let (crate_name, parts) = result.expect("crate name");
+        assert_eq!(crate_name, "café");
+        assert_eq!(parts, vec!["café", "Größe", "fmt"]);
+
+        let result = get_crate_name("<<日本::型 as core::ops::Fn>::call as 日本::呼>::f");
+        let (crate_name, _) = result.expect("crate name");
+        assert_eq!(crate_name, "日本");
+    }
+
+    #[test]
+    fn test_generic_trait() {
+        // The trait's own generic arguments don't affect the type or the outer method
+        for (symbol, method) in [
+            ("<foo::Bar as core::convert::From<baz::Qux>>::from", "from"),
+            (
+                "<foo::Bar as core::convert::TryFrom<[u8; 4]>>::try_from",
+                "try_from",
+            ),
+            (
+                "<foo::Bar as baz::Convert<baz::A, alloc::vec::Vec<(u8, u16)>>>::convert",
+                "convert",
+            ),
+        ] {
+            let (crate_name, parts) = get_crate_name(symbol).expect("crate name");
+            assert_eq!(crate_name, "foo", "{symbol}");
+            assert_eq!(parts, vec!["foo", "Bar", method], "{symbol}");
+        }
+    }
+
+    #[test]
+    fn test_generic_placeholder_type() {
+        // A generic parameter as the type falls back to the trait
+        for symbol in [
+            "<W as core::fmt::Write>::write_str",
+            "<&mut W as core::fmt::Write>::write_str",
+            "<_ as core::fmt::Write>::write_str",
+        ] {
+            let (crate_name, parts) = get_crate_name(symbol).expect("crate name");
+            assert_eq!(crate_name, "core", "{symbol}");
+            assert_eq!(parts, vec!["core", "fmt", "Write", "write_str"], "{symbol}");
+        }
+        let (crate_name, _) =
+            get_crate_name("<T as serde::de::Deserialize>::deserialize").expect("crate name");
+        assert_eq!(crate_name, "serde");
+        // Neither the type nor the trait names a crate
+        assert_eq!(get_crate_name("<T>::new"), None);
+        assert_eq!(get_crate_name("<T as U>::f"), None);
+        // Concrete references still use the type
+        let (crate_name, _) =
+            get_crate_name("<&mut alloc::vec::Vec<u8> as core::fmt::Write>::write_str")
+                .expect("crate name");
+        assert_eq!(crate_name, "alloc");
+    }
+
     #[test]
     fn test_get_crate_name_angle_bracket() {
         // Test trait impl: <&core::alloc::layout::Layout as core::fmt::Debug>::fmt
@@ -456,7 +1874,12 @@ mod test {
         assert!(result.is_some());
         let (crate_name, parts) = result.unwrap();
         assert_eq!(crate_name, "core");
-        assert_eq!(parts, vec!["core", "ffi", "c_void", "fmt"], "parts: {:?}", parts);
+        assert_eq!(
+            parts,
+            vec!["core", "ffi", "c_void", "fmt"],
+            "parts: {:?}",
+            parts
+        );
 
         // Test slice type [u8]
         let result = get_crate_name("<[u8] as core::fmt::Debug>::fmt");
@@ -471,7 +1894,9 @@ mod test {
         );
 
         // Test tuple type
-        let result = get_crate_name("<(swc_common::syntax_pos::Span, swc_ecma_parser::error::SyntaxError) as core::clone::Clone>::clone");
+        let result = get_crate_name(
+            "<(swc_common::syntax_pos::Span, swc_ecma_parser::error::SyntaxError) as core::clone::Clone>::clone",
+        );
         assert!(result.is_some());
         let (crate_name, parts) = result.unwrap();
         assert_eq!(crate_name, "std");
@@ -513,7 +1938,11 @@ mod test {
         assert_eq!(crate_name, "snmalloc");
         assert_eq!(
             parts,
-            vec!["snmalloc", "StandardConfigClientMeta", "initialisation_lock"],
+            vec![
+                "snmalloc",
+                "StandardConfigClientMeta",
+                "initialisation_lock"
+            ],
             "parts: {:?}",
             parts
         );
@@ -1,184 +1,936 @@
 use crate::{
     error::{BloatyError, Result},
+    options::{ConvertOptions, Weight},
     packages::Packages,
-    tool::{ROOT_NAME, SECTIONS_NAME, UNKNOWN_NAME, get_path_from_record},
+    report::{CrateStats, StatsReport},
+    rewrite::PathRewriter,
+    timings::Timings,
+    tool::{
+        FOLDED_NAME, MIXED_KIND, OTHERS_NAME, OVERFLOW_NAME, PADDING_NAME, PROC_MACRO_NAME,
+        ROOT_NAME, SECTIONS_NAME, UNKNOWN_NAME, erase_generics, get_path_and_crate, section_kind,
+    },
 };
-use cargo_lock::Lockfile;
-use serde::Deserialize;
+use cargo_lock::{Lockfile, dependency::Tree as DependencyTree};
+use serde::{Deserialize, Serialize};
 use serde_metafile::{Import, Input, InputDetail, Metafile, Output};
-use std::collections::HashMap;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    rc::Rc,
+    time::Instant,
+};
 
 /// Tree node representing a symbol or section in the binary
 /// Contains size information and child nodes
+/// Names are interned per tree, so the name and the key in the parent share one allocation
 #[derive(Debug, Clone)]
 pub struct Node {
-    pub name: Box<str>,
+    pub name: Rc<str>,
     pub vmsize: u64,
     pub filesize: u64,
     pub total_vmsize: u64,
     pub total_filesize: u64,
-    pub nodes: HashMap<Box<str>, Node>,
+    /// Number of records ending at this node
+    pub count: u64,
+    /// Number of records ending below this node
+    pub total_count: u64,
+    /// Kind of the symbols of a leaf (`code`, `rodata`, `data`, `bss`, `unwind` or `mixed`),
+    /// derived from their section, only set with `symbol_kinds`
+    pub kind: Option<&'static str>,
+    pub nodes: HashMap<Rc<str>, Node>,
 }
 
 impl Default for Node {
     fn default() -> Self {
         Self {
-            name: String::new().into_boxed_str(),
+            name: Rc::from(""),
             vmsize: 0,
             filesize: 0,
             total_vmsize: 0,
             total_filesize: 0,
+            count: 0,
+            total_count: 0,
+            kind: None,
             nodes: HashMap::new(),
         }
     }
 }
 
-/// CSV record from bloaty output
-/// Contains section name, symbol name, virtual memory size, and file size
-#[derive(Debug, Deserialize)]
+/// CSV record from bloaty output, one row of `bloaty -d sections,symbols --csv`
+/// Can also be constructed directly and passed to [`Tree::from_records`]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct SectionRecord {
+    /// Section name, e.g. `.text` or `__TEXT,__text`
     pub sections: String,
+    /// Values of extra dimensions between sections and symbols, in order
+    /// e.g. the compile unit of `bloaty -d sections,compileunits,symbols --csv`
+    #[serde(skip)]
+    pub dimensions: Vec<String>,
+    /// Symbol name, e.g. `core::fmt::write`
     pub symbols: String,
+    /// Virtual memory size in bytes
+    #[serde(deserialize_with = "deserialize_size")]
     pub vmsize: u64,
+    /// File size in bytes
+    #[serde(deserialize_with = "deserialize_size")]
     pub filesize: u64,
 }
 
+/// Deserialize a size in bytes, also accepting floats like `1086372.0` or `1.2e3` written by
+/// CSV post-processing tools, which are rounded to the nearest integer
+fn deserialize_size<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<u64, D::Error> {
+    struct SizeVisitor;
+
+    impl serde::de::Visitor<'_> for SizeVisitor {
+        type Value = u64;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a non-negative size in bytes")
+        }
+
+        fn visit_u64<E: serde::de::Error>(self, v: u64) -> std::result::Result<u64, E> {
+            Ok(v)
+        }
+
+        fn visit_i64<E: serde::de::Error>(self, v: i64) -> std::result::Result<u64, E> {
+            u64::try_from(v).map_err(|_| E::invalid_value(serde::de::Unexpected::Signed(v), &self))
+        }
+
+        fn visit_f64<E: serde::de::Error>(self, v: f64) -> std::result::Result<u64, E> {
+            if v.is_finite() && v >= 0.0 && v <= u64::MAX as f64 {
+                Ok(v.round() as u64)
+            } else {
+                Err(E::invalid_value(serde::de::Unexpected::Float(v), &self))
+            }
+        }
+
+        fn visit_str<E: serde::de::Error>(self, v: &str) -> std::result::Result<u64, E> {
+            let v = v.trim();
+            match v.parse::<u64>() {
+                Ok(size) => Ok(size),
+                Err(_) => match v.parse::<f64>() {
+                    Ok(size) => self.visit_f64(size),
+                    Err(_) => Err(E::invalid_value(serde::de::Unexpected::Str(v), &self)),
+                },
+            }
+        }
+    }
+
+    deserializer.deserialize_any(SizeVisitor)
+}
+
+/// How a symbol was attributed, one row of [`Tree::resolution_csv`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SymbolResolution {
+    pub symbol: String,
+    /// The crate or bucket (`SECTIONS`, `RUNTIME`, ...) the symbol is attributed to
+    #[serde(rename = "resolved_crate")]
+    pub crate_name: String,
+    /// The `/` separated path of the symbol in the tree, including the section
+    #[serde(rename = "resolved_path")]
+    pub path: String,
+}
+
 /// Hierarchical tree structure for organizing binary symbols and sections
 pub struct Tree {
     root: Node,
+    unattributed: Vec<String>,
+    resolution: Vec<SymbolResolution>,
+    traces: Vec<(String, String, String)>,
+    skipped: usize,
+    names: HashSet<Rc<str>>,
+    timings: Timings,
+    /// Own sizes of the symbols attributed to each crate (or bucket), wherever it is in the tree
+    crates: HashMap<Rc<str>, Node>,
+    depth_warnings: Vec<String>,
+    lockfile_mismatch: Option<(usize, usize)>,
+    lockfile_fallback: Option<String>,
 }
 
 impl Tree {
     /// Create a new tree from CSV data and optional Cargo.lock file
     /// Parses CSV records and builds a hierarchical structure
-    pub fn new(csv: &str, lock: Option<String>, no_sections: bool) -> Result<Tree> {
+    /// Returns `BloatyError::EmptyInput` if there are no data records, unless `allow_empty` is set
+    pub fn new(csv: &str, options: &ConvertOptions) -> Result<Tree> {
+        // Parse CSV records, rows with missing fields are skipped
+        let start = Instant::now();
+        let (records, skipped) = parse_records(csv, options)?;
+        let parse = start.elapsed();
+        let mut tree = Tree::build(records, options, None, None)?;
+        tree.skipped = skipped;
+        tree.timings.parse = parse;
+        Ok(tree)
+    }
+
+    /// Create a new tree from CSV data without loading any lockfile
+    /// Every crate is a top-level node instead of being placed in the dependency tree
+    pub fn new_no_lock(csv: &str, no_sections: bool) -> Result<Tree> {
+        let options = ConvertOptions {
+            no_lock: true,
+            no_sections,
+            ..Default::default()
+        };
+        Tree::new(csv, &options)
+    }
+
+    /// Like [`Tree::new`], applying `rewriter` to the path of every record
+    /// The crate of a record, `no_sections` and `exclude_proc_macros` still see the path
+    /// before it is rewritten
+    pub fn new_with_rewriter(
+        csv: &str,
+        options: &ConvertOptions,
+        rewriter: &dyn PathRewriter,
+    ) -> Result<Tree> {
+        let start = Instant::now();
+        let (records, skipped) = parse_records(csv, options)?;
+        let parse = start.elapsed();
+        let mut tree = Tree::build(records, options, Some(rewriter), None)?;
+        tree.skipped = skipped;
+        tree.timings.parse = parse;
+        Ok(tree)
+    }
+
+    /// Like [`Tree::new`], resolving crates with an already built dependency tree
+    /// No lockfile is loaded, `lock` and `no_lock` are ignored
+    pub fn new_with_tree(
+        csv: &str,
+        dep_tree: &DependencyTree,
+        options: &ConvertOptions,
+    ) -> Result<Tree> {
+        let start = Instant::now();
+        let (records, skipped) = parse_records(csv, options)?;
+        let parse = start.elapsed();
+        let mut tree = Tree::build(records, options, None, Some(dep_tree))?;
+        tree.skipped = skipped;
+        tree.timings.parse = parse;
+        Ok(tree)
+    }
+
+    /// Create a new tree from already parsed records and optional Cargo.lock file
+    /// Returns `BloatyError::EmptyInput` if there are no records, unless `allow_empty` is set
+    pub fn from_records(records: Vec<SectionRecord>, options: &ConvertOptions) -> Result<Tree> {
+        Tree::build(records, options, None, None)
+    }
+
+    /// Create a new tree from records and an already resolved dependency tree
+    /// No lockfile is loaded, `lock` and `no_lock` are ignored
+    pub fn from_records_with_tree(
+        records: Vec<SectionRecord>,
+        dep_tree: &DependencyTree,
+        options: &ConvertOptions,
+    ) -> Result<Tree> {
+        Tree::build(records, options, None, Some(dep_tree))
+    }
+
+    /// Build the tree from records, with an optional path rewriter
+    /// Packages are resolved from `dep_tree` if given, else from the lockfile in `options`
+    fn build(
+        records: Vec<SectionRecord>,
+        options: &ConvertOptions,
+        rewriter: Option<&dyn PathRewriter>,
+        dep_tree: Option<&DependencyTree>,
+    ) -> Result<Tree> {
         let mut tree = Tree {
             root: Node {
-                name: ROOT_NAME.to_string().into_boxed_str(),
+                name: Rc::from(ROOT_NAME),
                 vmsize: 0,
                 filesize: 0,
                 nodes: HashMap::new(),
                 total_filesize: 0,
                 total_vmsize: 0,
+                count: 0,
+                total_count: 0,
+                kind: None,
             },
+            unattributed: Vec::new(),
+            resolution: Vec::new(),
+            traces: Vec::new(),
+            skipped: 0,
+            names: HashSet::new(),
+            timings: Timings::default(),
+            crates: HashMap::new(),
+            depth_warnings: Vec::new(),
+            lockfile_mismatch: None,
+            lockfile_fallback: None,
         };
 
-        // Parse CSV records
-        let mut rdr = csv::Reader::from_reader(csv.as_bytes());
-        let records: Vec<_> = rdr
-            .deserialize::<SectionRecord>()
-            .collect::<std::result::Result<Vec<_>, csv::Error>>()
-            .map_err(BloatyError::CsvParse)?;
+        if records.is_empty() && !options.allow_empty {
+            return Err(BloatyError::EmptyInput);
+        }
 
         // Load Cargo.lock and resolve package dependencies
-        let lock_path = lock.unwrap_or_else(|| "Cargo.lock".to_string());
-        let packages = Lockfile::load(&lock_path)
-            .map_err(|source| BloatyError::LockfileLoad {
-                path: lock_path.clone(),
-                source,
-            })
-            .and_then(|lock| {
-                lock.dependency_tree()
-                    .map_err(|source| BloatyError::LockfileLoad {
-                        path: lock_path.clone(),
-                        source,
-                    })
+        // Without a lockfile every crate is a top-level node, a lockfile that was asked for
+        // explicitly must load
+        let start = Instant::now();
+        let lock_path = options
+            .lock
+            .clone()
+            .unwrap_or_else(|| "Cargo.lock".to_string());
+        let packages = if let Some(dep_tree) = dep_tree {
+            Packages::new(dep_tree, &records, options)
+        } else if options.no_lock {
+            Packages::flat(options)
+        } else {
+            let packages = Lockfile::load(&lock_path)
+                .map_err(|source| BloatyError::LockfileLoad {
+                    path: lock_path.clone(),
+                    source,
+                })
+                .and_then(|lock| {
+                    lock.dependency_tree()
+                        .map_err(|source| BloatyError::LockfileLoad {
+                            path: lock_path.clone(),
+                            source,
+                        })
+                })
+                .map(|dep_tree| Packages::new(&dep_tree, &records, options));
+            match packages {
+                Ok(packages) => packages,
+                Err(e) if options.lock.is_some() => return Err(e),
+                Err(e) => {
+                    let reason = std::error::Error::source(&e)
+                        .map(|source| format!("{e}: {source}"))
+                        .unwrap_or_else(|| e.to_string());
+                    tree.lockfile_fallback = Some(reason);
+                    Packages::flat(options)
+                }
+            }
+        };
+        tree.timings.lockfile = start.elapsed();
+        tree.lockfile_mismatch = packages.lockfile_mismatch();
+        if let Some((matched, total)) = tree.lockfile_mismatch
+            && options.strict
+        {
+            return Err(BloatyError::LockfileMismatch { matched, total });
+        }
+        let (median, outliers) = packages.depth_outliers();
+        tree.depth_warnings = outliers
+            .into_iter()
+            .map(|(name, depth)| {
+                format!("crate `{name}` is at depth {depth}, the median crate depth is {median}")
             })
-            .map(|dep_tree| Packages::new(&dep_tree, &records))
-            .unwrap_or_default();
+            .collect();
 
         // Build tree from records
+        let start = Instant::now();
+        let mut unattributed = BTreeSet::new();
+        let mut resolution: BTreeMap<String, (String, String)> = BTreeMap::new();
+        let mut section_sums: HashMap<String, (u64, u64)> = HashMap::new();
+        let in_sections = |section: &str| {
+            options.only_sections.is_empty() || options.only_sections.iter().any(|s| s == section)
+        };
         for record in records {
+            if !in_sections(&record.sections) {
+                continue;
+            }
+            if !options.section_sizes.is_empty() {
+                let sum = section_sums.entry(record.sections.clone()).or_default();
+                sum.0 += record.vmsize;
+                sum.1 += record.filesize;
+            }
             let sym = if record.symbols.is_empty() {
                 UNKNOWN_NAME.to_string()
             } else {
                 record.symbols
             };
-            let path = get_path_from_record(sym, record.sections, &packages);
-            if no_sections && path[0] == SECTIONS_NAME {
+            let symbol = (options.collect_unattributed && sym != UNKNOWN_NAME).then(|| sym.clone());
+            let resolved = (options.collect_resolution
+                && sym != UNKNOWN_NAME
+                && !resolution.contains_key(&sym))
+            .then(|| sym.clone());
+            let trace = options
+                .trace_symbol
+                .as_deref()
+                .filter(|filter| sym.contains(filter))
+                .map(|_| (record.sections.clone(), sym.clone()));
+            let sym = options
+                .symbol_regex
+                .iter()
+                .fold(sym, |sym, rule| match rule.apply(&sym) {
+                    Cow::Borrowed(_) => sym,
+                    Cow::Owned(rewritten) => rewritten,
+                });
+            let kind = options
+                .symbol_kinds
+                .then(|| section_kind(&record.sections))
+                .flatten();
+            let (vmsize, filesize) = (record.vmsize, record.filesize);
+            // The rewriter gets the record with the rewritten symbol
+            let (mut path, crate_len, record) = match rewriter {
+                Some(_) => {
+                    let record = SectionRecord {
+                        symbols: sym,
+                        ..record
+                    };
+                    let (path, crate_len) = get_path_and_crate(
+                        record.symbols.clone(),
+                        record.sections.clone(),
+                        &record.dimensions,
+                        &packages,
+                        options,
+                    );
+                    (path, crate_len, Some(record))
+                }
+                None => {
+                    let (path, crate_len) = get_path_and_crate(
+                        sym,
+                        record.sections,
+                        &record.dimensions,
+                        &packages,
+                        options,
+                    );
+                    (path, crate_len, None)
+                }
+            };
+            // The crate and the filters are decided on the path before the rewriter, which can
+            // move or drop the crate level: `crate_len` only describes the original path
+            let crate_name = path_crate(&path, crate_len).to_string();
+            // The bucket or crate, after the levels of extra dimensions if there are any
+            let bucket = path_crate(&path, crate_len);
+            let is_unattributed = bucket == SECTIONS_NAME;
+            let excluded = (options.no_sections
+                && (bucket == SECTIONS_NAME || bucket == OTHERS_NAME))
+                || (options.exclude_proc_macros
+                    && crate_len >= 2
+                    && path
+                        .get(crate_len - 2)
+                        .is_some_and(|p| p == PROC_MACRO_NAME));
+            if let (Some(rewriter), Some(record)) = (rewriter, &record) {
+                rewriter.rewrite(&mut path, record);
+            }
+            if let Some((sections, sym)) = trace {
+                tree.traces.push((sections, sym, path.join("/")));
+            }
+            if path.is_empty() {
                 continue;
             }
-            tree.add_path(&path, record.vmsize, record.filesize);
+            if let Some(symbol) = resolved {
+                resolution.insert(symbol, (crate_name.clone(), path.join("/")));
+            }
+            if let Some(symbol) = symbol
+                && is_unattributed
+            {
+                unattributed.insert(symbol);
+            }
+            if excluded {
+                continue;
+            }
+            tree.add_path(&path, vmsize, filesize, kind);
+            tree.add_crate(&crate_name, vmsize, filesize);
+        }
+        // Alignment padding and other bytes of a section that no symbol accounts for
+        for (section, &(vmsize, filesize)) in &options.section_sizes {
+            let (sum_vmsize, sum_filesize) = section_sums.get(section).copied().unwrap_or_default();
+            let vmsize = vmsize.saturating_sub(sum_vmsize);
+            let filesize = filesize.saturating_sub(sum_filesize);
+            if (vmsize == 0 && filesize == 0) || options.no_sections || !in_sections(section) {
+                continue;
+            }
+            let (path, crate_len) = get_path_and_crate(
+                PADDING_NAME.to_string(),
+                section.clone(),
+                &[],
+                &packages,
+                options,
+            );
+            let kind = options
+                .symbol_kinds
+                .then(|| section_kind(section))
+                .flatten();
+            tree.add_path(&path, vmsize, filesize, kind);
+            tree.add_crate(path_crate(&path, crate_len), vmsize, filesize);
         }
+        if options.merge_generic_siblings {
+            let Tree { root, names, .. } = &mut tree;
+            root.merge_generic_siblings(names);
+        }
+        if options.prune_empty {
+            tree.root.prune_empty(options.weight);
+        }
+        tree.unattributed = unattributed.into_iter().collect();
+        tree.resolution = resolution
+            .into_iter()
+            .map(|(symbol, (crate_name, path))| SymbolResolution {
+                symbol,
+                crate_name,
+                path,
+            })
+            .collect();
+        tree.timings.build = start.elapsed();
 
         Ok(tree)
     }
 
+    /// Total size of the symbols attributed to each crate, sorted by name
+    /// Unlike the tree, a crate's total doesn't include its dependencies, and crates are found
+    /// wherever they are in the dependency tree. Symbols without a crate are counted under
+    /// their bucket (`SECTIONS`, `RUNTIME`, `OTHERS`)
+    pub fn crate_totals(&self, weight: Weight) -> Vec<(String, u64)> {
+        let mut totals: Vec<_> = self
+            .crates
+            .values()
+            .map(|node| (node.name.to_string(), node.weight(weight)))
+            .collect();
+        totals.sort();
+        totals
+    }
+
+    /// Symbol count, file size and average symbol size of each crate (or bucket), counted like
+    /// [`Tree::crate_totals`], sorted by size (largest first), then by name
+    pub fn crate_stats(&self) -> StatsReport {
+        let mut crates: Vec<CrateStats> = self
+            .crates
+            .values()
+            .map(|node| CrateStats {
+                name: node.name.to_string(),
+                symbols: node.count,
+                bytes: node.filesize,
+            })
+            .collect();
+        crates.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+        StatsReport { crates }
+    }
+
+    /// Unique symbols (sorted) that could not be attributed to a crate and ended up in SECTIONS
+    /// Only collected if `collect_unattributed` is set
+    pub fn unattributed(&self) -> &[String] {
+        &self.unattributed
+    }
+
+    /// Unique symbols (sorted) with the crate and path they were attributed to, for the first
+    /// record of each symbol. Only collected if `collect_resolution` is set
+    pub fn resolution(&self) -> &[SymbolResolution] {
+        &self.resolution
+    }
+
+    /// `(section, symbol, path)` of every record whose symbol contains
+    /// `ConvertOptions::trace_symbol`, in input order
+    pub fn traces(&self) -> &[(String, String, String)] {
+        &self.traces
+    }
+
+    /// [`Tree::resolution`] as CSV with a `symbol,resolved_crate,resolved_path` header
+    pub fn resolution_csv(&self) -> Result<String> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        for row in &self.resolution {
+            writer.serialize(row)?;
+        }
+        let bytes = writer
+            .into_inner()
+            .map_err(|e| csv::Error::from(e.into_error()))?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// How long parsing, loading the lockfile and building the tree took
+    /// `convert` and `serialize` are left at zero, they are up to the caller
+    pub fn timings(&self) -> Timings {
+        self.timings
+    }
+
+    /// Depth of the deepest node, top-level nodes (crates) have depth 0 like in `deep`
+    pub fn max_depth(&self) -> usize {
+        fn depth(node: &Node) -> usize {
+            node.nodes
+                .values()
+                .map(|child| depth(child) + 1)
+                .max()
+                .unwrap_or(0)
+        }
+        self.root.nodes.values().map(depth).max().unwrap_or(0)
+    }
+
+    /// Number of CSV rows skipped because they had fewer fields than required
+    pub fn skipped_records(&self) -> usize {
+        self.skipped
+    }
+
+    /// Crates placed much deeper in the dependency tree than the median crate, a hint that the
+    /// dependency graph was resolved in a confusing way, see [`Packages::depth_outliers`]
+    /// Purely informational, the tree is built the same way
+    pub fn depth_warnings(&self) -> &[String] {
+        &self.depth_warnings
+    }
+
+    /// `(found, total)` if few of the crates in the CSV are in the lockfile, which usually means
+    /// it is the lockfile of another project, see `ConvertOptions::strict`
+    pub fn lockfile_mismatch(&self) -> Option<(usize, usize)> {
+        self.lockfile_mismatch
+    }
+
+    /// Why the implicit `./Cargo.lock` could not be used, in which case every crate is a
+    /// top-level node. A lockfile set with `ConvertOptions::lock` is an error instead
+    pub fn lockfile_fallback(&self) -> Option<&str> {
+        self.lockfile_fallback.as_deref()
+    }
+
     /// Convert the tree to an esbuild metafile format
     /// Traverses the tree and generates the metafile structure
-    pub fn to_metafile(&self, name: &str, deep: usize) -> Metafile {
+    pub fn to_metafile(&self, name: &str, options: &ConvertOptions) -> Metafile {
         let root = &self.root;
+        let nodes: Vec<_> = root.nodes.values().collect();
+        build_metafile(&nodes, name, options)
+    }
 
-        // Pre-allocate HashMap with estimated capacity
-        let mut inputs = HashMap::with_capacity(root.nodes.len() * 4);
+    /// Convert only the subtree of the crate (or any node) named `root` to a metafile,
+    /// with that node as the single top-level input
+    /// The shallowest node with the name is used, e.g. `app/foo` rather than `app/.text/foo`
+    /// Returns `BloatyError::NodeNotFound` if there is no such node
+    pub fn to_metafile_rooted_at(
+        &self,
+        root: &str,
+        name: &str,
+        options: &ConvertOptions,
+    ) -> Result<Metafile> {
+        let node = self
+            .find_node(root)
+            .ok_or_else(|| BloatyError::NodeNotFound {
+                name: root.to_string(),
+            })?;
+        Ok(build_metafile(&[node], name, options))
+    }
 
-        // Traverse all root nodes to build inputs
-        for node in root.nodes.values() {
-            node.traverse(&mut inputs, None, deep);
+    /// Find the shallowest node named `name`, children of a level are searched in name order
+    fn find_node(&self, name: &str) -> Option<&Node> {
+        let mut level: Vec<&Node> = vec![&self.root];
+        while !level.is_empty() {
+            let mut next: Vec<&Node> = level.iter().flat_map(|node| node.nodes.values()).collect();
+            next.sort_by(|a, b| a.name.cmp(&b.name));
+            if let Some(node) = next.iter().find(|node| node.name.as_ref() == name) {
+                return Some(node);
+            }
+            level = next;
         }
+        None
+    }
 
-        // Build output_inputs using iterator chain
-        let output_inputs: HashMap<_, _> = inputs
-            .iter()
-            .map(|(path, input)| {
-                (
-                    path.clone(),
-                    InputDetail {
-                        bytes_in_output: input.bytes,
-                    },
-                )
+    /// Convert each top-level node (crate or SECTIONS) to its own standalone metafile
+    /// Returns `(node name, metafile)` pairs sorted by name
+    pub fn to_crate_metafiles(&self, options: &ConvertOptions) -> Vec<(String, Metafile)> {
+        let mut metafiles: Vec<_> = self
+            .root
+            .nodes
+            .values()
+            .map(|node| {
+                let meta = build_metafile(&[node], &node.name, options);
+                (node.name.to_string(), meta)
             })
             .collect();
+        metafiles.sort_by(|a, b| a.0.cmp(&b.0));
+        metafiles
+    }
 
-        let output = Output {
-            bytes: root.total_filesize,
-            inputs: output_inputs,
-            imports: vec![],
-            exports: vec![],
-            entry_point: None,
-            css_bundle: None,
-        };
+    /// Convert the tree to a recursive `{name, vmsize, filesize, children}` JSON structure
+    /// Sizes are the node's own sizes, so summing a subtree gives its total
+    pub fn to_nested_json(&self) -> serde_json::Value {
+        self.root.to_nested_json()
+    }
+
+    /// Convert the tree to the recursive `{name, data: {"$area": size}, children}` JSON of
+    /// webtreemap, the area of a node is its total size measured by `weight`
+    pub fn to_webtreemap_json(&self, weight: Weight) -> serde_json::Value {
+        self.root.to_webtreemap_json(weight)
+    }
+
+    /// Convert the tree to an uncompressed pprof profile (protobuf), for flamegraph tools
+    /// Every node with an own size is a sample whose stack is the node's path
+    #[cfg(feature = "pprof")]
+    pub fn to_pprof(&self) -> Vec<u8> {
+        crate::pprof::encode(&self.root)
+    }
 
-        let outputs = HashMap::from([(name.to_string(), output)]);
-        Metafile { inputs, outputs }
+    /// Add the size of a symbol to the total of its crate
+    fn add_crate(&mut self, name: &str, vmsize: u64, filesize: u64) {
+        let name = intern(&mut self.names, name);
+        let node = self
+            .crates
+            .entry(name.clone())
+            .or_insert_with(|| Node::create_node(name, 0, 0, true));
+        node.vmsize += vmsize;
+        node.filesize += filesize;
+        node.count += 1;
     }
 
     /// Add a path to the tree with associated size information
     /// Creates intermediate nodes as needed
-    fn add_path(&mut self, path: &[String], vmsize: u64, filesize: u64) {
-        let mut current = &mut self.root;
-        let last_idx = path.len() - 1;
+    /// The leaf gets `kind`, or `mixed` if it already has another kind
+    fn add_path(
+        &mut self,
+        path: &[String],
+        vmsize: u64,
+        filesize: u64,
+        kind: Option<&'static str>,
+    ) {
+        let Tree { root, names, .. } = self;
+        let mut current = root;
+        let Some(last_idx) = path.len().checked_sub(1) else {
+            return;
+        };
 
         for (i, part) in path.iter().enumerate() {
             current.total_vmsize += vmsize;
             current.total_filesize += filesize;
+            current.total_count += 1;
 
             let is_leaf = i == last_idx;
-            let part_boxed: Box<str> = part.as_str().into();
-
-            // Use entry API to avoid double lookup
-            current = current.nodes.entry(part_boxed.clone()).or_insert_with(|| {
-                Node::create_node(
-                    part_boxed.clone(),
-                    0, // Initialize with 0, will be accumulated below
-                    0,
-                    is_leaf,
-                )
-            });
+
+            // Only intern the name when a new node is created
+            if !current.nodes.contains_key(part.as_str()) {
+                let name = intern(names, part);
+                // Initialize with 0, will be accumulated below
+                let node = Node::create_node(name.clone(), 0, 0, is_leaf);
+                current.nodes.insert(name, node);
+            }
+            current = current
+                .nodes
+                .get_mut(part.as_str())
+                .expect("node exists after insert");
 
             // Accumulate leaf node values (don't overwrite)
             if is_leaf {
                 current.vmsize += vmsize;
                 current.filesize += filesize;
+                current.count += 1;
+                current.kind = merge_kind(current.kind, kind);
+            }
+        }
+    }
+}
+
+/// Number of fields in a `SectionRecord` row
+const SECTION_RECORD_FIELDS: usize = 4;
+
+/// Known bloaty CSV column layouts (ignoring percentage columns and column order)
+const KNOWN_LAYOUTS: &[&[&str]] = &[&["sections", "symbols", "vmsize", "filesize"]];
+
+/// Columns of a `SectionRecord`, any other column is an extra dimension
+const RECORD_COLUMNS: &[&str] = &["sections", "symbols", "vmsize", "filesize"];
+
+/// Size columns, bloaty puts the dimension columns before them
+const SIZE_COLUMNS: &[&str] = &["vmsize", "filesize"];
+
+/// Check that the CSV header (without percentage columns) is a known bloaty layout
+/// Extra dimensions are allowed between `sections` and `symbols`, e.g.
+/// `sections,compileunits,symbols,vmsize,filesize`
+fn validate_header(headers: &csv::StringRecord) -> Result<()> {
+    let mut columns: Vec<&str> = headers.iter().map(str::trim).collect();
+    let dimensions: Vec<&str> = columns
+        .iter()
+        .copied()
+        .filter(|c| !SIZE_COLUMNS.contains(c))
+        .collect();
+    if let [first, extra @ .., last] = dimensions.as_slice()
+        && *first == "sections"
+        && *last == "symbols"
+    {
+        columns.retain(|c| !extra.contains(c));
+    }
+    columns.sort_unstable();
+    let known = KNOWN_LAYOUTS.iter().any(|layout| {
+        let mut layout = layout.to_vec();
+        layout.sort_unstable();
+        layout == columns
+    });
+    if known {
+        return Ok(());
+    }
+    Err(BloatyError::UnrecognizedFormat {
+        header: headers.iter().collect::<Vec<_>>().join(","),
+        expected: KNOWN_LAYOUTS
+            .iter()
+            .map(|layout| layout.join(","))
+            .collect::<Vec<_>>()
+            .join(" or "),
+    })
+}
+
+/// Check if a CSV column is a percentage column like `vmsize%` or `filesize%`
+#[inline]
+fn is_percentage_column(name: &str) -> bool {
+    name.trim().ends_with('%')
+}
+
+/// Parse CSV data into section records, returning the records and the number of skipped rows
+/// Without a header, columns map positionally to sections,symbols,vmsize,filesize
+/// With a header, columns are selected by name and percentage columns are ignored
+/// Dimension columns between sections and symbols go to `SectionRecord::dimensions`
+/// Rows with fewer fields than required (e.g. a trailing summary line) are skipped
+fn parse_records(csv: &str, options: &ConvertOptions) -> Result<(Vec<SectionRecord>, usize)> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(!options.no_header)
+        .flexible(true)
+        .from_reader(csv.as_bytes());
+    let mut records = Vec::new();
+    let mut skipped = 0;
+
+    if options.no_header {
+        for record in rdr.records() {
+            let record = record?;
+            if record.len() < SECTION_RECORD_FIELDS {
+                skipped += 1;
+                continue;
             }
+            records.push(record.deserialize::<SectionRecord>(None)?);
+        }
+        return Ok((records, skipped));
+    }
+
+    let headers = rdr.headers()?.clone();
+    let keep: Vec<usize> = headers
+        .iter()
+        .enumerate()
+        .filter(|(_, name)| !is_percentage_column(name))
+        .map(|(i, _)| i)
+        .collect();
+    let columns: csv::StringRecord = keep.iter().map(|&i| &headers[i]).collect();
+    // Completely empty input has no header, it is reported as `EmptyInput` instead
+    if !columns.is_empty() {
+        validate_header(&columns)?;
+    }
+    let (keep, dimensions): (Vec<usize>, Vec<usize>) = keep
+        .into_iter()
+        .partition(|&i| RECORD_COLUMNS.contains(&headers[i].trim()));
+    let columns: csv::StringRecord = keep.iter().map(|&i| &headers[i]).collect();
+
+    for record in rdr.records() {
+        let record = record?;
+        let fields: csv::StringRecord = keep.iter().filter_map(|&i| record.get(i)).collect();
+        if fields.len() < columns.len() || dimensions.iter().any(|&i| record.get(i).is_none()) {
+            skipped += 1;
+            continue;
         }
+        let mut section_record = fields.deserialize::<SectionRecord>(Some(&columns))?;
+        section_record.dimensions = dimensions.iter().map(|&i| record[i].to_string()).collect();
+        records.push(section_record);
+    }
+    Ok((records, skipped))
+}
+
+/// Section sizes from `bloaty -d sections --csv`, as `section -> (vmsize, filesize)`
+/// for [`ConvertOptions::section_sizes`]
+/// Percentage columns are ignored and rows with missing fields are skipped
+pub fn parse_section_sizes(csv: &str) -> Result<HashMap<String, (u64, u64)>> {
+    #[derive(Deserialize)]
+    struct SectionSize {
+        sections: String,
+        #[serde(deserialize_with = "deserialize_size")]
+        vmsize: u64,
+        #[serde(deserialize_with = "deserialize_size")]
+        filesize: u64,
+    }
+    let mut rdr = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(csv.as_bytes());
+    let headers = rdr.headers()?.clone();
+    let mut sizes = HashMap::new();
+    for record in rdr.records() {
+        let record = record?;
+        if record.len() < headers.len() {
+            continue;
+        }
+        let size: SectionSize = record.deserialize(Some(&headers))?;
+        sizes.insert(size.sections, (size.vmsize, size.filesize));
+    }
+    Ok(sizes)
+}
+
+/// The crate (or bucket) of a path, the last of its first `crate_len` levels
+/// Empty if the path is empty
+#[inline]
+fn path_crate(path: &[String], crate_len: usize) -> &str {
+    path.get(crate_len.min(path.len()).saturating_sub(1))
+        .map_or("", String::as_str)
+}
+
+/// Kind of a leaf with symbols of kinds `a` and `b`, `mixed` if they differ
+#[inline]
+fn merge_kind(a: Option<&'static str>, b: Option<&'static str>) -> Option<&'static str> {
+    match (a, b) {
+        (Some(a), Some(b)) if a != b => Some(MIXED_KIND),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Return the shared copy of `s`, adding it to `names` if it is not there yet
+#[inline]
+fn intern(names: &mut HashSet<Rc<str>>, s: &str) -> Rc<str> {
+    if let Some(name) = names.get(s) {
+        return name.clone();
     }
+    let name: Rc<str> = Rc::from(s);
+    names.insert(name.clone());
+    name
+}
+
+/// Sort nodes by total `weight`, largest first, and nodes of the same size by name
+fn sort_largest_first(nodes: &mut [&Node], weight: Weight) {
+    nodes.sort_by(|a, b| {
+        b.total_weight(weight)
+            .cmp(&a.total_weight(weight))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+}
+
+/// Build a metafile with a single output from the given top-level nodes
+/// The output `bytes` and `bytesInOutput` are measured by `options.output_weight()`
+fn build_metafile(nodes: &[&Node], name: &str, options: &ConvertOptions) -> Metafile {
+    let mut nodes = nodes.to_vec();
+    sort_largest_first(&mut nodes, options.weight);
+
+    // Pre-allocate HashMaps with estimated capacity
+    let mut out = MetafileInputs::new(options.max_inputs, nodes.len() * 4);
+
+    // Traverse all top-level nodes to build inputs, each with its own depth limit
+    let mut entries = Vec::with_capacity(nodes.len());
+    for node in &nodes {
+        let deep = options
+            .deep_overrides
+            .get(node.name.as_ref())
+            .copied()
+            .unwrap_or(options.deep);
+        if node.traverse(&mut out, None, 0, deep, options) {
+            entries.push(node.name.to_string());
+        }
+    }
+    if out.overflow.is_some() {
+        entries.push(OVERFLOW_NAME.to_string());
+    }
+    let (mut inputs, output_inputs) = out.finish();
+    let output_weight = options.output_weight();
+    let bytes = nodes
+        .iter()
+        .map(|node| node.total_weight(output_weight))
+        .sum();
+
+    // Reference the top-level inputs from the output, like esbuild entry inputs
+    let output_imports = if options.emit_imports {
+        entries.into_iter().map(import).collect()
+    } else {
+        vec![]
+    };
+
+    let output = Output {
+        bytes,
+        inputs: output_inputs,
+        imports: output_imports,
+        exports: vec![],
+        entry_point: None,
+        css_bundle: None,
+    };
+
+    let outputs = HashMap::from([(name.to_string(), output)]);
+    // The per-input sizes are still in the output's inputs
+    if options.outputs_only {
+        inputs.clear();
+    }
+    Metafile { inputs, outputs }
 }
 
 impl Node {
     /// Helper function to create a new node with given parameters
     #[inline]
-    fn create_node(name: Box<str>, vmsize: u64, filesize: u64, is_leaf: bool) -> Self {
+    fn create_node(name: Rc<str>, vmsize: u64, filesize: u64, is_leaf: bool) -> Self {
         Self {
             name,
             vmsize,
@@ -190,12 +942,169 @@ impl Node {
             },
             total_filesize: 0,
             total_vmsize: 0,
+            count: 0,
+            total_count: 0,
+            kind: None,
+        }
+    }
+
+    /// Recursively merge sibling leaves whose names are equal after [`erase_generics`], e.g. the
+    /// monomorphizations `<u8>` and `<u16>` of a function become one `<_>` leaf with their sum
+    fn merge_generic_siblings(&mut self, names: &mut HashSet<Rc<str>>) {
+        let mut groups: HashMap<String, Vec<Rc<str>>> = HashMap::new();
+        for (key, child) in &self.nodes {
+            if child.nodes.is_empty()
+                && let Cow::Owned(erased) = erase_generics(key)
+            {
+                groups.entry(erased).or_default().push(key.clone());
+            }
+        }
+        for (name, keys) in groups {
+            if keys.len() < 2 {
+                continue;
+            }
+            let (mut vmsize, mut filesize, mut count, mut kind) = (0, 0, 0, None);
+            for key in &keys {
+                if let Some(leaf) = self.nodes.remove(key) {
+                    vmsize += leaf.vmsize;
+                    filesize += leaf.filesize;
+                    count += leaf.count;
+                    kind = merge_kind(kind, leaf.kind);
+                }
+            }
+            // A sibling may already have the merged name, e.g. a module with that name
+            let name = intern(names, &name);
+            let merged = self
+                .nodes
+                .entry(name.clone())
+                .or_insert_with(|| Node::create_node(name, 0, 0, true));
+            merged.vmsize += vmsize;
+            merged.filesize += filesize;
+            merged.count += count;
+            merged.kind = merge_kind(merged.kind, kind);
+        }
+        for child in self.nodes.values_mut() {
+            if !child.nodes.is_empty() {
+                child.merge_generic_siblings(names);
+            }
+        }
+    }
+
+    /// Recursively remove the children whose total `weight` is zero, e.g. symbols with a vm
+    /// size but no file size, updating the totals of the other sizes
+    /// Returns the removed `(vmsize, filesize, count)`
+    fn prune_empty(&mut self, weight: Weight) -> (u64, u64, u64) {
+        let mut removed = (0, 0, 0);
+        self.nodes.retain(|_, child| {
+            let empty = child.total_weight(weight) == 0;
+            if empty {
+                removed.0 += child.vmsize + child.total_vmsize;
+                removed.1 += child.filesize + child.total_filesize;
+                removed.2 += child.count + child.total_count;
+            }
+            !empty
+        });
+        for child in self.nodes.values_mut() {
+            let (vmsize, filesize, count) = child.prune_empty(weight);
+            removed.0 += vmsize;
+            removed.1 += filesize;
+            removed.2 += count;
+        }
+        self.total_vmsize -= removed.0;
+        self.total_filesize -= removed.1;
+        self.total_count -= removed.2;
+        removed
+    }
+
+    /// Recursively convert the node and its children (sorted by name) to nested JSON
+    /// Leaves with a kind get a `kind` field
+    fn to_nested_json(&self) -> serde_json::Value {
+        let mut children: Vec<_> = self.nodes.values().collect();
+        children.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut json = serde_json::json!({
+            "name": self.name.as_ref(),
+            "vmsize": self.vmsize,
+            "filesize": self.filesize,
+            "children": children.into_iter().map(Node::to_nested_json).collect::<Vec<_>>(),
+        });
+        if let Some(kind) = self.kind {
+            json["kind"] = kind.into();
+        }
+        json
+    }
+
+    /// Recursively convert the node and its children (largest first) to webtreemap JSON
+    /// Leaves have no `children` field
+    fn to_webtreemap_json(&self, weight: Weight) -> serde_json::Value {
+        let mut json = serde_json::json!({
+            "name": self.name.as_ref(),
+            "data": { "$area": self.total_weight(weight) },
+        });
+        if !self.nodes.is_empty() {
+            let mut children: Vec<_> = self.nodes.values().collect();
+            sort_largest_first(&mut children, weight);
+            json["children"] = children
+                .into_iter()
+                .map(|child| child.to_webtreemap_json(weight))
+                .collect();
+        }
+        json
+    }
+
+    /// Own size of the node, file size, vm size or record count
+    #[inline]
+    fn weight(&self, weight: Weight) -> u64 {
+        match weight {
+            Weight::Bytes => self.filesize,
+            Weight::Vmsize => self.vmsize,
+            Weight::Count => self.count,
+        }
+    }
+
+    /// Total size of the node and its children, file size, vm size or record count
+    #[inline]
+    fn total_weight(&self, weight: Weight) -> u64 {
+        match weight {
+            Weight::Bytes => self.filesize + self.total_filesize,
+            Weight::Vmsize => self.vmsize + self.total_vmsize,
+            Weight::Count => self.count + self.total_count,
         }
     }
 
     /// Recursively traverse the tree to build metafile inputs
-    /// Respects the depth limit if specified
-    fn traverse(&self, inputs: &mut HashMap<String, Input>, dir: Option<String>, deep: usize) {
+    /// Respects the depth limit if specified, `depth` is the depth of this node (0 for top-level nodes)
+    /// With `max_children`, only the largest children are kept and the rest are folded into `[others]`
+    /// Returns false if the node was folded into `[overflow]` because `max_inputs` was reached
+    fn traverse(
+        &self,
+        out: &mut MetafileInputs,
+        dir: Option<String>,
+        depth: usize,
+        deep: usize,
+        options: &ConvertOptions,
+    ) -> bool {
+        let ConvertOptions {
+            max_children,
+            weight,
+            collapse_after,
+            ..
+        } = *options;
+        let output_weight = options.output_weight();
+
+        // Check if we're at the depth limit
+        let at_depth_limit = deep != 0 && depth >= deep;
+        // Past `collapse_after` the sizes are carried by the collapsed ancestor, this node is kept
+        // with zero size; the node at `collapse_after` carries its subtree total like at the depth limit
+        let collapsed = collapse_after != 0 && depth > collapse_after;
+        let rolled_up = at_depth_limit || (collapse_after != 0 && depth == collapse_after);
+
+        if out.is_full() {
+            if !collapsed {
+                out.overflow(self.total_weight(weight), self.total_weight(output_weight));
+            }
+            return false;
+        }
+
         // Build directory path with capacity pre-allocation
         let dir: String = match &dir {
             Some(parent) => {
@@ -208,62 +1117,152 @@ impl Node {
             None => self.name.to_string(),
         };
 
-        let current_depth = dir.matches('/').count();
-
-        // Check if we're at the depth limit
-        let at_depth_limit = deep != 0 && current_depth >= deep;
-
-        // Build imports (only if not at depth limit)
-        let imports: Vec<Import> = if at_depth_limit {
+        // Children to visit (none at depth limit), the smallest beyond max_children are folded
+        // Largest first and ties by name, so the imports have the same order on every run
+        let mut children: Vec<&Node> = if at_depth_limit {
             vec![]
         } else {
-            self.nodes
-                .values()
-                .map(|child| {
-                    let mut import_path = String::with_capacity(dir.len() + 1 + child.name.len());
-                    import_path.push_str(&dir);
-                    import_path.push('/');
-                    import_path.push_str(&child.name);
-                    Import {
-                        path: import_path,
-                        kind: None,
-                        external: false,
-                        original: None,
-                        with: None,
-                    }
-                })
-                .collect()
+            self.nodes.values().collect()
         };
-
-        // Use total_filesize when at depth limit to include all children's sizes
-        let bytes = if at_depth_limit {
-            self.total_filesize
+        sort_largest_first(&mut children, weight);
+        let folded = if max_children != 0 && children.len() > max_children {
+            let rest = &children[max_children..];
+            let folded: u64 = rest.iter().map(|c| c.total_weight(weight)).sum();
+            let output: u64 = rest.iter().map(|c| c.total_weight(output_weight)).sum();
+            children.truncate(max_children);
+            if collapse_after != 0 && depth >= collapse_after {
+                Some((0, 0))
+            } else {
+                Some((folded, output))
+            }
         } else {
-            self.filesize
+            None
         };
 
+        // Include all children's sizes (and the node's own size) when at depth limit
+        let size = |weight| {
+            if collapsed {
+                0
+            } else if rolled_up {
+                self.total_weight(weight)
+            } else {
+                self.weight(weight)
+            }
+        };
+        out.insert(dir.clone(), size(weight), size(output_weight));
+
+        // Recurse into the kept children (empty at depth limit), the imports are the children
+        // that were not folded into `[overflow]`
+        let mut imports: Vec<String> = Vec::with_capacity(children.len() + 2);
+        let mut overflowed = false;
+        for child in children {
+            if child.traverse(out, Some(dir.clone()), depth + 1, deep, options) {
+                imports.push(format!("{dir}/{}", child.name));
+            } else {
+                overflowed = true;
+            }
+        }
+        if let Some((bytes, bytes_in_output)) = folded {
+            if out.is_full() {
+                out.overflow(bytes, bytes_in_output);
+                overflowed = true;
+            } else {
+                let path = format!("{dir}/{FOLDED_NAME}");
+                out.insert(path.clone(), bytes, bytes_in_output);
+                imports.push(path);
+            }
+        }
+        if overflowed {
+            imports.push(OVERFLOW_NAME.to_string());
+        }
+        if let Some(input) = out.inputs.get_mut(&dir) {
+            input.imports = imports.into_iter().map(import).collect();
+        }
+        true
+    }
+}
+
+/// The inputs of a metafile while it is built, with the `max_inputs` budget
+struct MetafileInputs {
+    inputs: HashMap<String, Input>,
+    output_inputs: HashMap<String, InputDetail>,
+    /// Inputs left before the rest is folded into `[overflow]`, None means unlimited
+    remaining: Option<usize>,
+    /// Size and output size folded into `[overflow]`, None if nothing was folded
+    overflow: Option<(u64, u64)>,
+}
+
+impl MetafileInputs {
+    /// One input of the budget is kept for `[overflow]`
+    fn new(max_inputs: usize, capacity: usize) -> Self {
+        Self {
+            inputs: HashMap::with_capacity(capacity),
+            output_inputs: HashMap::with_capacity(capacity),
+            remaining: (max_inputs != 0).then(|| max_inputs - 1),
+            overflow: None,
+        }
+    }
+
+    /// Returns true if the remaining nodes go to `[overflow]`
+    fn is_full(&self) -> bool {
+        self.remaining == Some(0)
+    }
+
+    /// Add an input without imports
+    fn insert(&mut self, path: String, bytes: u64, bytes_in_output: u64) {
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining.saturating_sub(1);
+        }
+        self.output_inputs
+            .insert(path.clone(), InputDetail { bytes_in_output });
         let input = Input {
             bytes,
-            imports,
+            imports: vec![],
             format: None,
             with: None,
         };
+        self.inputs.insert(path, input);
+    }
 
-        inputs.insert(dir.clone(), input);
+    /// Fold the size of a node or `[others]` into `[overflow]`
+    fn overflow(&mut self, bytes: u64, bytes_in_output: u64) {
+        let (total, total_in_output) = self.overflow.get_or_insert((0, 0));
+        *total += bytes;
+        *total_in_output += bytes_in_output;
+    }
 
-        // Recurse into children only if not at depth limit
-        if !at_depth_limit && !self.nodes.is_empty() {
-            let dir_ref = Some(dir);
-            for child in self.nodes.values() {
-                child.traverse(inputs, dir_ref.clone(), deep);
-            }
+    /// Add the `[overflow]` input if anything was folded
+    fn finish(mut self) -> (HashMap<String, Input>, HashMap<String, InputDetail>) {
+        if let Some((bytes, bytes_in_output)) = self.overflow {
+            self.remaining = None;
+            self.insert(OVERFLOW_NAME.to_string(), bytes, bytes_in_output);
         }
+        (self.inputs, self.output_inputs)
+    }
+}
+
+/// An import of the input at `path`
+fn import(path: String) -> Import {
+    Import {
+        path,
+        kind: None,
+        external: false,
+        original: None,
+        with: None,
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::tree::Tree;
+    use crate::{
+        diff::diff_crates,
+        error::BloatyError,
+        options::ConvertOptions,
+        options::Weight,
+        tree::{SectionRecord, Tree, parse_section_sizes, path_crate},
+    };
+    use serde_metafile::Metafile;
+    use std::{rc::Rc, time::Duration};
 
     #[test]
     fn test_get_tree() {
@@ -277,8 +1276,1251 @@ sections,symbols,vmsize,filesize
 .text,[1843 Others],1086372,1086372
 "#,
         ] {
-            let tree = Tree::new(csv, None, false).expect("Failed to create tree");
-            assert_eq!(tree.root.nodes.len(), 1)
+            let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+            assert_eq!(tree.root.nodes.len(), 1);
+            assert!(tree.root.nodes.contains_key("OTHERS"));
+        }
+    }
+
+    #[test]
+    fn test_empty_input() {
+        for csv in ["", "sections,symbols,vmsize,filesize\n"] {
+            let result = Tree::new(csv, &ConvertOptions::default());
+            assert!(matches!(result, Err(BloatyError::EmptyInput)));
+
+            let options = ConvertOptions {
+                allow_empty: true,
+                ..Default::default()
+            };
+            let tree = Tree::new(csv, &options).expect("Failed to create tree");
+            assert!(tree.root.nodes.is_empty());
+            assert_eq!(tree.root.total_filesize, 0);
+        }
+    }
+
+    #[test]
+    fn test_to_crate_metafiles() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,10,10
+.text,foo::b,20,20
+.text,bar::c,5,5
+.text,main,1,1
+"#;
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        let metafiles = tree.to_crate_metafiles(&ConvertOptions::default());
+        let names: Vec<_> = metafiles.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["SECTIONS", "bar", "foo"]);
+
+        let (_, foo) = &metafiles[2];
+        assert_eq!(foo.outputs["foo"].bytes, 30);
+        assert!(foo.inputs.keys().all(|path| path.starts_with("foo")));
+        assert_eq!(foo.inputs["foo/.text/a"].bytes, 10);
+    }
+
+    #[test]
+    fn test_to_nested_json() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,10,12
+.text,foo::b,20,20
+"#;
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        let json = tree.to_nested_json();
+        assert_eq!(json["name"], "ROOT");
+        let foo = &json["children"][0];
+        assert_eq!(foo["name"], "foo");
+        let text = &foo["children"][0];
+        assert_eq!(text["name"], ".text");
+        assert_eq!(text["children"][0]["name"], "a");
+        assert_eq!(text["children"][0]["vmsize"], 10);
+        assert_eq!(text["children"][0]["filesize"], 12);
+        assert_eq!(text["children"][0]["children"], serde_json::json!([]));
+        assert_eq!(text["children"][1]["name"], "b");
+    }
+
+    #[test]
+    fn test_to_webtreemap_json() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,10,12
+.text,foo::b,20,20
+.text,bar::c,5,5
+"#;
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        let json = tree.to_webtreemap_json(Weight::Bytes);
+        assert_eq!(json["name"], "ROOT");
+        assert_eq!(json["data"]["$area"], 37);
+        let foo = &json["children"][0];
+        assert_eq!(foo["name"], "foo");
+        assert_eq!(foo["data"]["$area"], 32);
+        let text = &foo["children"][0];
+        assert_eq!(text["name"], ".text");
+        assert_eq!(
+            text["children"],
+            serde_json::json!([
+                { "name": "b", "data": { "$area": 20 } },
+                { "name": "a", "data": { "$area": 12 } },
+            ])
+        );
+        assert_eq!(json["children"][1]["name"], "bar");
+
+        let json = tree.to_webtreemap_json(Weight::Vmsize);
+        assert_eq!(json["data"]["$area"], 35);
+    }
+
+    #[test]
+    fn test_depth_ignores_slash_in_names() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a/b::c,10,10
+"#;
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        // foo (0) / .text (1) / a/b (2) / c (3)
+        let options = ConvertOptions {
+            deep: 3,
+            ..Default::default()
+        };
+        let meta = tree.to_metafile("BINARY", &options);
+        assert_eq!(meta.inputs["foo/.text/a/b"].imports.len(), 1);
+        assert_eq!(meta.inputs["foo/.text/a/b/c"].bytes, 10);
+
+        let options = ConvertOptions {
+            deep: 2,
+            ..Default::default()
+        };
+        let meta = tree.to_metafile("BINARY", &options);
+        assert!(meta.inputs["foo/.text/a/b"].imports.is_empty());
+        assert_eq!(meta.inputs["foo/.text/a/b"].bytes, 10);
+        assert!(!meta.inputs.contains_key("foo/.text/a/b/c"));
+    }
+
+    #[test]
+    fn test_collapse_after_vs_deep() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,1,1
+.text,foo::a::b,2,2
+.text,foo::a::b::c,4,4
+.text,foo::d,8,8
+.text,bar::e,16,16
+"#;
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        let deep = tree.to_metafile(
+            "BINARY",
+            &ConvertOptions {
+                deep: 2,
+                ..Default::default()
+            },
+        );
+        let collapsed = tree.to_metafile(
+            "BINARY",
+            &ConvertOptions {
+                collapse_after: 2,
+                ..Default::default()
+            },
+        );
+
+        // --deep stops at depth 2, --collapse-after keeps the deeper nodes with zero size
+        assert!(!deep.inputs.contains_key("foo/.text/a/b"));
+        assert_eq!(collapsed.inputs["foo/.text/a/b"].bytes, 0);
+        assert_eq!(collapsed.inputs["foo/.text/a/b/c"].bytes, 0);
+        assert_eq!(
+            collapsed.inputs["foo/.text/a"].imports[0].path,
+            "foo/.text/a/b"
+        );
+
+        // Nodes present in both have the same size, the node at the depth carries its subtree
+        for (path, input) in &deep.inputs {
+            assert_eq!(collapsed.inputs[path].bytes, input.bytes, "{path}");
+        }
+        assert_eq!(collapsed.inputs["foo/.text/a"].bytes, 7);
+        assert_eq!(collapsed.inputs.len(), deep.inputs.len() + 2);
+        let total = |meta: &Metafile| meta.inputs.values().map(|i| i.bytes).sum::<u64>();
+        assert_eq!(total(&collapsed), 31);
+        assert_eq!(total(&deep), 31);
+    }
+
+    #[test]
+    fn test_deep_overrides() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a::b,10,10
+.text,bar::a::b,20,20
+"#;
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        let options = ConvertOptions {
+            deep: 1,
+            deep_overrides: [("bar".to_string(), 0)].into(),
+            ..Default::default()
+        };
+        let meta = tree.to_metafile("BINARY", &options);
+        // foo falls back to the global depth
+        assert_eq!(meta.inputs["foo/.text"].bytes, 10);
+        assert!(!meta.inputs.contains_key("foo/.text/a"));
+        // bar is fully expanded
+        assert_eq!(meta.inputs["bar/.text/a/b"].bytes, 20);
+    }
+
+    #[test]
+    fn test_skip_malformed_rows() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,10,10
+.text,foo::b,20,20
+TOTAL,30
+"#;
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        assert_eq!(tree.skipped_records(), 1);
+        assert_eq!(
+            tree.to_metafile("BINARY", &Default::default()).inputs.len(),
+            4
+        );
+
+        let csv = ".text,foo::a,10,10\nTOTAL,10\n";
+        let options = ConvertOptions {
+            no_header: true,
+            ..Default::default()
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        assert_eq!(tree.skipped_records(), 1);
+    }
+
+    #[test]
+    fn test_sections_as_leaves() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a::b,10,10
+.text,foo::c,20,20
+.rodata,foo::d,5,5
+.text,unknown_symbol,7,7
+"#;
+        let options = ConvertOptions {
+            sections_as_leaves: true,
+            ..Default::default()
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        let meta = tree.to_metafile("BINARY", &options);
+        assert_eq!(meta.inputs["foo/.text"].bytes, 30);
+        assert!(meta.inputs["foo/.text"].imports.is_empty());
+        assert_eq!(meta.inputs["foo/.rodata"].bytes, 5);
+        assert_eq!(meta.inputs["SECTIONS/.text"].bytes, 7);
+        assert_eq!(meta.inputs.len(), 5);
+    }
+
+    #[test]
+    fn test_explicit_lock_must_load() {
+        let csv = "sections,symbols,vmsize,filesize\n.text,foo::a,1,1\n";
+        let options = ConvertOptions {
+            lock: Some("missing.lock".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Tree::new(csv, &options),
+            Err(BloatyError::LockfileLoad { .. })
+        ));
+
+        let tree = Tree::new_no_lock(csv, false).expect("Failed to create tree");
+        assert!(tree.lockfile_fallback().is_none());
+        assert!(tree.root.nodes.contains_key("foo"));
+    }
+
+    #[test]
+    fn test_from_records() {
+        let record = |symbols: &str, size| SectionRecord {
+            sections: ".text".to_string(),
+            symbols: symbols.to_string(),
+            vmsize: size,
+            filesize: size,
+            ..Default::default()
+        };
+        let records = vec![record("foo::a", 10), record("foo::b", 20)];
+        let tree =
+            Tree::from_records(records, &ConvertOptions::default()).expect("Failed to create tree");
+        let meta = tree.to_metafile("BINARY", &ConvertOptions::default());
+        assert_eq!(meta.inputs["foo/.text/a"].bytes, 10);
+        assert_eq!(meta.outputs["BINARY"].bytes, 30);
+
+        assert!(matches!(
+            Tree::from_records(vec![], &ConvertOptions::default()),
+            Err(BloatyError::EmptyInput)
+        ));
+    }
+
+    #[test]
+    fn test_max_children() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,10,10
+.text,foo::b::x,15,15
+.text,foo::b::y,5,5
+.text,foo::c,30,30
+.text,foo::d,40,40
+"#;
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        let options = ConvertOptions {
+            max_children: 2,
+            ..Default::default()
+        };
+        let meta = tree.to_metafile("BINARY", &options);
+        // d (40) and c (30) are kept, a (10) and b (20) are folded
+        let mut imports: Vec<_> = meta.inputs["foo/.text"]
+            .imports
+            .iter()
+            .map(|i| i.path.as_str())
+            .collect();
+        imports.sort();
+        assert_eq!(
+            imports,
+            ["foo/.text/[others]", "foo/.text/c", "foo/.text/d"]
+        );
+        assert_eq!(meta.inputs["foo/.text/[others]"].bytes, 30);
+        assert!(!meta.inputs.contains_key("foo/.text/b"));
+        assert!(!meta.inputs.contains_key("foo/.text/b/x"));
+
+        let total: u64 = meta.inputs.values().map(|input| input.bytes).sum();
+        assert_eq!(total, meta.outputs["BINARY"].bytes);
+    }
+
+    #[test]
+    fn test_max_inputs() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,30,30
+.text,foo::b,20,20
+.text,foo::c,10,10
+.text,bar::d,5,5
+"#;
+        let options = ConvertOptions {
+            no_lock: true,
+            max_inputs: 4,
+            emit_imports: true,
+            ..Default::default()
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        let meta = tree.to_metafile("BINARY", &options);
+        // foo, foo/.text and foo/.text/a are kept, b, c and bar are folded
+        let mut paths: Vec<_> = meta.inputs.keys().map(String::as_str).collect();
+        paths.sort();
+        assert_eq!(paths, ["[overflow]", "foo", "foo/.text", "foo/.text/a"]);
+        assert_eq!(meta.inputs["[overflow]"].bytes, 35);
+        let imports: Vec<_> = meta.inputs["foo/.text"]
+            .imports
+            .iter()
+            .map(|i| i.path.as_str())
+            .collect();
+        assert_eq!(imports, ["foo/.text/a", "[overflow]"]);
+        let output = &meta.outputs["BINARY"];
+        let entries: Vec<_> = output.imports.iter().map(|i| i.path.as_str()).collect();
+        assert_eq!(entries, ["foo", "[overflow]"]);
+
+        let total: u64 = meta.inputs.values().map(|input| input.bytes).sum();
+        assert_eq!(total, 65);
+        assert_eq!(total, output.bytes);
+        let in_output: u64 = output.inputs.values().map(|i| i.bytes_in_output).sum();
+        assert_eq!(in_output, 65);
+
+        // Nothing is folded below the limit
+        let options = ConvertOptions {
+            max_inputs: 100,
+            ..options
+        };
+        let meta = tree.to_metafile("BINARY", &options);
+        assert!(!meta.inputs.contains_key("[overflow]"));
+        assert_eq!(meta.inputs.len(), 8);
+    }
+
+    #[test]
+    fn test_multiple_dimensions() {
+        let csv = r#"
+sections,compileunits,symbols,vmsize,filesize,filesize%
+.text,a.rs,foo::a::f,10,10,10%
+.text,b.rs,foo::b,20,20,20%
+.rodata,a.rs,unknown_symbol,7,7,7%
+"#;
+        let options = ConvertOptions {
+            no_lock: true,
+            no_sections: true,
+            ..Default::default()
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        let meta = tree.to_metafile("BINARY", &options);
+        // section -> compile unit -> crate/symbol
+        assert_eq!(meta.inputs[".text/a.rs/foo/a/f"].bytes, 10);
+        assert_eq!(meta.inputs[".text/b.rs/foo/b"].bytes, 20);
+        assert_eq!(meta.inputs[".text"].imports.len(), 2);
+        // Unattributed symbols are still dropped by `no_sections` below the prefix
+        assert!(
+            !meta
+                .inputs
+                .contains_key(".rodata/a.rs/SECTIONS/unknown_symbol")
+        );
+        assert_eq!(
+            tree.crate_totals(Weight::Bytes),
+            vec![("foo".to_string(), 30)]
+        );
+
+        // Extra dimensions must be between sections and symbols
+        let csv = "compileunits,sections,symbols,vmsize,filesize\na.rs,.text,foo::a,10,10\n";
+        assert!(matches!(
+            Tree::new(csv, &options),
+            Err(BloatyError::UnrecognizedFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_crate_stats() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,1,100
+.text,foo::b,1,200
+.text,foo::c,1,301
+.text,bar::d,1,1000
+"#;
+        let options = ConvertOptions {
+            no_lock: true,
+            ..Default::default()
+        };
+        let stats = Tree::new(csv, &options)
+            .expect("Failed to create tree")
+            .crate_stats();
+        let names: Vec<_> = stats.crates.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["bar", "foo"]);
+        assert_eq!(stats.crates[1].symbols, 3);
+        assert_eq!(stats.crates[1].bytes, 601);
+        assert_eq!(stats.crates[1].average(), 200);
+        assert_eq!(stats.crates[0].average(), 1000);
+        assert_eq!(
+            stats.to_string(),
+            concat!(
+                "crate  symbols  bytes  average\n",
+                "bar          1   1000     1000\n",
+                "foo          3    601      200\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_merge_generic_siblings() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,core::ptr::drop_in_place<foo::A>,1,10
+.text,core::ptr::drop_in_place<foo::B>,2,20
+.text,core::ptr::drop_in_place<bar::C<u8>>,4,40
+.text,core::ptr::read,8,80
+"#;
+        let options = ConvertOptions {
+            no_lock: true,
+            merge_generic_siblings: true,
+            ..Default::default()
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        let meta = tree.to_metafile("BINARY", &options);
+        assert_eq!(meta.inputs["core/.text/ptr/drop_in_place<_>"].bytes, 70);
+        assert_eq!(meta.inputs["core/.text/ptr/read"].bytes, 80);
+        assert_eq!(meta.inputs["core/.text/ptr"].imports.len(), 2);
+        assert!(
+            !meta
+                .inputs
+                .contains_key("core/.text/ptr/drop_in_place<foo::A>")
+        );
+        assert_eq!(meta.outputs["BINARY"].bytes, 150);
+
+        let options = ConvertOptions {
+            weight: Weight::Count,
+            ..options
+        };
+        let meta = Tree::new(csv, &options)
+            .expect("Failed to create tree")
+            .to_metafile("BINARY", &options);
+        assert_eq!(meta.inputs["core/.text/ptr/drop_in_place<_>"].bytes, 3);
+    }
+
+    #[test]
+    fn test_prune_empty() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,10,10
+.text,foo::b,5,0
+.bss,foo::c,20,0
+.bss,bar::d,8,0
+"#;
+        let options = ConvertOptions {
+            no_lock: true,
+            prune_empty: true,
+            ..Default::default()
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        let foo = &tree.root.nodes["foo"];
+        assert!(foo.nodes[".text"].nodes.contains_key("a"));
+        assert!(!foo.nodes[".text"].nodes.contains_key("b"));
+        // Interior nodes without any file size go too
+        assert!(!foo.nodes.contains_key(".bss"));
+        assert!(!tree.root.nodes.contains_key("bar"));
+        // The vm size totals only count the remaining nodes
+        assert_eq!(foo.total_vmsize, 10);
+        assert_eq!(tree.root.total_vmsize, 10);
+        assert_eq!(tree.root.total_count, 1);
+
+        // With the vm size weight nothing is empty
+        let options = ConvertOptions {
+            weight: Weight::Vmsize,
+            ..options
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        assert_eq!(tree.root.total_vmsize, 43);
+        assert!(tree.root.nodes.contains_key("bar"));
+    }
+
+    #[test]
+    fn test_exclude_proc_macros() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,10,10
+.text,foo_derive::expand,20,20
+"#;
+        let options = ConvertOptions {
+            no_lock: true,
+            proc_macros: vec!["foo-derive".to_string()],
+            ..Default::default()
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        let meta = tree.to_metafile("BINARY", &options);
+        assert_eq!(
+            meta.inputs["[proc-macro]/foo_derive/.text/expand"].bytes,
+            20
+        );
+        assert_eq!(meta.outputs["BINARY"].bytes, 30);
+
+        let options = ConvertOptions {
+            exclude_proc_macros: true,
+            ..options
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        let meta = tree.to_metafile("BINARY", &options);
+        assert!(!meta.inputs.contains_key("[proc-macro]"));
+        assert_eq!(meta.outputs["BINARY"].bytes, 10);
+        assert_eq!(
+            tree.crate_totals(Weight::Bytes),
+            vec![("foo".to_string(), 10)]
+        );
+    }
+
+    #[test]
+    fn test_only_sections() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,10,10
+.rodata,foo::b,20,20
+.debug_info,foo::c,40,40
+.data,bar::d,80,80
+"#;
+        let options = ConvertOptions {
+            no_lock: true,
+            only_sections: vec![".text".to_string(), ".rodata".to_string()],
+            ..Default::default()
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        let meta = tree.to_metafile("BINARY", &options);
+        assert_eq!(meta.inputs["foo/.text/a"].bytes, 10);
+        assert_eq!(meta.inputs["foo/.rodata/b"].bytes, 20);
+        assert!(!meta.inputs.contains_key("foo/.debug_info/c"));
+        assert!(!meta.inputs.contains_key("bar"));
+        assert_eq!(meta.outputs["BINARY"].bytes, 30);
+    }
+
+    #[test]
+    fn test_section_padding() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,10,10
+.text,foo::b,20,20
+.data,bar::c,5,5
+"#;
+        let sections = "sections,vmsize,filesize\n.text,32,40\n.data,5,5\n.bss,8,0\n";
+        let options = ConvertOptions {
+            no_lock: true,
+            section_sizes: parse_section_sizes(sections).expect("Failed to parse sections"),
+            ..Default::default()
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        let meta = tree.to_metafile("BINARY", &options);
+        assert_eq!(meta.inputs["SECTIONS/.text/[padding]"].bytes, 10);
+        assert_eq!(meta.inputs["SECTIONS/.bss/[padding]"].bytes, 0);
+        // Fully covered sections get no padding
+        assert!(!meta.inputs.contains_key("SECTIONS/.data/[padding]"));
+        // The inputs add up to the reported section sizes
+        assert_eq!(meta.outputs["BINARY"].bytes, 45);
+
+        let options = ConvertOptions {
+            weight: Weight::Vmsize,
+            ..options
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        let meta = tree.to_metafile("BINARY", &options);
+        assert_eq!(meta.inputs["SECTIONS/.bss/[padding]"].bytes, 8);
+        assert_eq!(meta.outputs["BINARY"].bytes, 45);
+    }
+
+    #[test]
+    fn test_flatten_sections() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a::f,10,10
+.rodata,foo::a::f,5,5
+.text,foo::a::g,20,20
+.text,unknown_symbol,7,7
+"#;
+        let options = ConvertOptions {
+            flatten_sections: true,
+            ..Default::default()
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        let meta = tree.to_metafile("BINARY", &options);
+        assert_eq!(meta.inputs["foo"].imports.len(), 1);
+        assert_eq!(meta.inputs["foo"].imports[0].path, "foo/a");
+        assert_eq!(meta.inputs["foo/a/f"].bytes, 15);
+        assert_eq!(meta.inputs["foo/a/g"].bytes, 20);
+        // Unattributed symbols keep their section
+        assert_eq!(meta.inputs["SECTIONS/.text/unknown_symbol"].bytes, 7);
+    }
+
+    #[test]
+    fn test_header_layout() {
+        // Column order doesn't matter
+        let csv = "symbols,sections,filesize,vmsize\nfoo::a,.text,10,10\n";
+        assert!(Tree::new(csv, &ConvertOptions::default()).is_ok());
+
+        let csv = "compileunits,symbols,vmsize,filesize\nfoo.rs,foo::a,10,10\n";
+        match Tree::new(csv, &ConvertOptions::default()) {
+            Err(BloatyError::UnrecognizedFormat { header, expected }) => {
+                assert_eq!(header, "compileunits,symbols,vmsize,filesize");
+                assert_eq!(expected, "sections,symbols,vmsize,filesize");
+            }
+            other => panic!("expected UnrecognizedFormat, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_weight_count() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a::f,100,100
+.text,foo::a::g,200,200
+.text,foo::a::g,300,300
+.text,foo::b,400,400
+.text,bar::c,500,500
+"#;
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        assert_eq!(tree.root.total_count, 5);
+        assert_eq!(tree.root.nodes["foo"].total_count, 4);
+        assert_eq!(
+            tree.root.nodes["foo"].nodes[".text"].nodes["a"].total_count,
+            3
+        );
+
+        let options = ConvertOptions {
+            weight: Weight::Count,
+            ..Default::default()
+        };
+        let meta = tree.to_metafile("BINARY", &options);
+        assert_eq!(meta.inputs["foo/.text/a/g"].bytes, 2);
+        assert_eq!(meta.inputs["foo/.text/b"].bytes, 1);
+        assert_eq!(meta.outputs["BINARY"].bytes, 5);
+
+        let options = ConvertOptions {
+            weight: Weight::Count,
+            deep: 2,
+            ..Default::default()
+        };
+        let meta = tree.to_metafile("BINARY", &options);
+        assert_eq!(meta.inputs["foo/.text/a"].bytes, 3);
+    }
+
+    #[test]
+    fn test_output_weight() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,100,10
+.text,foo::b,200,20
+.bss,bar::c,300,0
+"#;
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        let options = ConvertOptions {
+            output_weight: Some(Weight::Vmsize),
+            ..Default::default()
+        };
+        let meta = tree.to_metafile("BINARY", &options);
+        let output = &meta.outputs["BINARY"];
+        assert_eq!(meta.inputs["foo/.text/b"].bytes, 20);
+        assert_eq!(output.inputs["foo/.text/b"].bytes_in_output, 200);
+        assert_eq!(meta.inputs["bar/.bss/c"].bytes, 0);
+        assert_eq!(output.inputs["bar/.bss/c"].bytes_in_output, 300);
+        assert_eq!(output.bytes, 600);
+        assert_eq!(
+            output
+                .inputs
+                .values()
+                .map(|d| d.bytes_in_output)
+                .sum::<u64>(),
+            600
+        );
+
+        // Folded children are measured by both weights too
+        let options = ConvertOptions {
+            max_children: 1,
+            ..options
+        };
+        let meta = tree.to_metafile("BINARY", &options);
+        assert_eq!(meta.inputs["foo/.text/[others]"].bytes, 10);
+        assert_eq!(
+            meta.outputs["BINARY"].inputs["foo/.text/[others]"].bytes_in_output,
+            100
+        );
+    }
+
+    #[test]
+    fn test_merge_pe_sections() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text$mn,foo::a,1,1
+.text$x,foo::b,2,2
+.text$mn$00,foo::a,4,4
+.rdata$r,foo::c,8,8
+.CRT$XCU,foo::d,16,16
+.CRT$XCA,foo::d,32,32
+"#;
+        let options = ConvertOptions {
+            merge_pe_sections: true,
+            ..Default::default()
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        let foo = &tree.root.nodes["foo"];
+        let mut sections: Vec<_> = foo.nodes.keys().map(|s| s.as_ref()).collect();
+        sections.sort();
+        assert_eq!(sections, vec![".CRT", ".rdata", ".text"]);
+        assert_eq!(foo.nodes[".text"].nodes["a"].filesize, 5);
+        assert_eq!(foo.nodes[".text"].total_filesize, 7);
+        assert_eq!(foo.nodes[".CRT"].nodes["d"].filesize, 48);
+
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        assert_eq!(tree.root.nodes["foo"].nodes.len(), 6);
+    }
+
+    #[test]
+    fn test_rooted_at() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,10,10
+.text,foo::b::c,20,20
+.text,bar::foo,5,5
+"#;
+        let options = ConvertOptions::default();
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        let meta = tree
+            .to_metafile_rooted_at("foo", "BINARY", &options)
+            .expect("foo exists");
+        assert!(meta.inputs.keys().all(|path| path.starts_with("foo")));
+        assert!(meta.inputs.contains_key("foo/.text/b/c"));
+        assert_eq!(meta.outputs["BINARY"].bytes, 30);
+        let total: u64 = meta.inputs.values().map(|input| input.bytes).sum();
+        assert_eq!(total, 30);
+
+        assert!(matches!(
+            tree.to_metafile_rooted_at("baz", "BINARY", &options),
+            Err(BloatyError::NodeNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_no_header() {
+        let rows = ".text,foo::a,10,10\n.text,main,5,5\n";
+        let headered = Tree::new(
+            &format!("sections,symbols,vmsize,filesize\n{rows}"),
+            &ConvertOptions::default(),
+        )
+        .expect("Failed to create tree");
+        let options = ConvertOptions {
+            no_header: true,
+            ..Default::default()
+        };
+        let headerless = Tree::new(rows, &options).expect("Failed to create tree");
+        assert_eq!(headerless.root.total_filesize, 15);
+        assert_eq!(headerless.to_nested_json(), headered.to_nested_json());
+    }
+
+    #[test]
+    fn test_emit_imports() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,10,10
+.text,bar::b,20,20
+"#;
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        let meta = tree.to_metafile("BINARY", &ConvertOptions::default());
+        assert!(meta.outputs["BINARY"].imports.is_empty());
+
+        let options = ConvertOptions {
+            emit_imports: true,
+            ..Default::default()
+        };
+        let meta = tree.to_metafile("BINARY", &options);
+        let mut imports: Vec<_> = meta.outputs["BINARY"]
+            .imports
+            .iter()
+            .map(|import| import.path.as_str())
+            .collect();
+        imports.sort();
+        assert_eq!(imports, vec!["bar", "foo"]);
+        assert!(imports.iter().all(|path| meta.inputs.contains_key(*path)));
+    }
+
+    #[test]
+    fn test_outputs_only() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,10,10
+.text,bar::b,20,20
+"#;
+        let full = Tree::new(csv, &ConvertOptions::default())
+            .expect("Failed to create tree")
+            .to_metafile("BINARY", &ConvertOptions::default());
+        let options = ConvertOptions {
+            outputs_only: true,
+            ..Default::default()
+        };
+        let meta = Tree::new(csv, &options)
+            .expect("Failed to create tree")
+            .to_metafile("BINARY", &options);
+        assert!(meta.inputs.is_empty());
+        let output = &meta.outputs["BINARY"];
+        assert_eq!(output.bytes, 30);
+        assert_eq!(output.inputs.len(), full.inputs.len());
+        assert_eq!(output.inputs["foo/.text/a"].bytes_in_output, 10);
+        assert_eq!(output.inputs["bar"].bytes_in_output, 0);
+    }
+
+    #[test]
+    fn test_unattributed() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,10,10
+.text,main,5,5
+.data,main,5,5
+.text,_$LT$alloc..string..String$GT$,5,5
+.text,[10 Others],5,5
+.text,,5,5
+"#;
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        assert!(tree.unattributed().is_empty());
+
+        let options = ConvertOptions {
+            collect_unattributed: true,
+            ..Default::default()
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        assert_eq!(
+            tree.unattributed(),
+            ["_$LT$alloc..string..String$GT$", "main"]
+        );
+    }
+
+    #[test]
+    fn test_group_by_version_major() {
+        let dep_tree = crate::packages::test::dependency_tree(&[
+            ("app", "0.1.0", &["rand 0.8.5", "foo 1.0.0"]),
+            ("foo", "1.0.0", &["rand 0.9.0"]),
+            ("rand", "0.8.5", &[]),
+            ("rand", "0.9.0", &[]),
+        ]);
+        let record = |unit: &str, filesize| SectionRecord {
+            sections: ".text".to_string(),
+            dimensions: vec![format!("/registry/src/index.crates.io-1/{unit}/src/lib.rs")],
+            symbols: "rand::f".to_string(),
+            vmsize: filesize,
+            filesize,
+        };
+        let records = vec![record("rand-0.8.5", 10), record("rand-0.9.0", 20)];
+        let options = ConvertOptions {
+            group_by_version_major: true,
+            ..Default::default()
+        };
+        let tree = Tree::from_records_with_tree(records, &dep_tree, &options)
+            .expect("Failed to create tree");
+        // Both major versions are in the tree, each with the bytes of its own symbols
+        assert_eq!(
+            tree.crate_totals(Weight::Bytes),
+            [("rand v0.8".to_string(), 10), ("rand v0.9".to_string(), 20)]
+        );
+    }
+
+    #[test]
+    fn test_lockfile_mismatch() {
+        let dep_tree = crate::packages::test::dependency_tree(&[("other", "0.1.0", &[])]);
+        let records = crate::packages::test::records(&["app::main", "foo::f"]);
+        let tree =
+            Tree::from_records_with_tree(records.clone(), &dep_tree, &ConvertOptions::default())
+                .expect("Failed to create tree");
+        assert_eq!(tree.lockfile_mismatch(), Some((0, 2)));
+
+        let options = ConvertOptions {
+            strict: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            Tree::from_records_with_tree(records, &dep_tree, &options),
+            Err(BloatyError::LockfileMismatch {
+                matched: 0,
+                total: 2
+            })
+        ));
+        // Without a lockfile there is nothing to check
+        let tree = Tree::new_no_lock(
+            "sections,symbols,vmsize,filesize\n.text,foo::f,1,1\n",
+            false,
+        )
+        .expect("Failed to create tree");
+        assert_eq!(tree.lockfile_mismatch(), None);
+    }
+
+    #[test]
+    fn test_symbol_kinds() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::f,10,10
+.rodata,foo::TABLE,20,20
+.bss,foo::STATE,30,0
+.debug_info,foo::g,5,5
+.text,foo::h,1,1
+.rodata,foo::h,1,1
+"#;
+        let options = ConvertOptions {
+            no_lock: true,
+            symbol_kinds: true,
+            flatten_sections: true,
+            ..Default::default()
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        let foo = &tree.root.nodes["foo"];
+        assert_eq!(foo.kind, None);
+        assert_eq!(foo.nodes["f"].kind, Some("code"));
+        assert_eq!(foo.nodes["TABLE"].kind, Some("rodata"));
+        assert_eq!(foo.nodes["STATE"].kind, Some("bss"));
+        assert_eq!(foo.nodes["g"].kind, None);
+        assert_eq!(foo.nodes["h"].kind, Some("mixed"));
+
+        let nested = tree.to_nested_json();
+        let f = &nested["children"][0]["children"]
+            .as_array()
+            .expect("children")
+            .iter()
+            .find(|c| c["name"] == "f")
+            .expect("f")
+            .clone();
+        assert_eq!(f["kind"], "code");
+        assert!(nested.get("kind").is_none());
+
+        // Off by default
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        assert_eq!(tree.root.nodes["foo"].nodes[".text"].nodes["f"].kind, None);
+    }
+
+    #[test]
+    fn test_traces() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::clone::structured_clone,10,10
+.text,bar::other,5,5
+"#;
+        let options = ConvertOptions {
+            no_lock: true,
+            trace_symbol: Some("structured".to_string()),
+            ..Default::default()
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        assert_eq!(
+            tree.traces(),
+            [(
+                ".text".to_string(),
+                "foo::clone::structured_clone".to_string(),
+                "foo/.text/clone/structured_clone".to_string()
+            )]
+        );
+
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        assert!(tree.traces().is_empty());
+    }
+
+    #[test]
+    fn test_resolution() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,10,10
+.data,foo::a,10,10
+.text,main,5,5
+.text,<bar::B as core::fmt::Debug>::fmt,5,5
+.text,[10 Others],5,5
+.text,,5,5
+"#;
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        assert!(tree.resolution().is_empty());
+
+        let options = ConvertOptions {
+            no_lock: true,
+            collect_resolution: true,
+            ..Default::default()
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        assert_eq!(
+            tree.resolution_csv().expect("Failed to write CSV"),
+            "symbol,resolved_crate,resolved_path
+<bar::B as core::fmt::Debug>::fmt,bar,bar/.text/B/fmt
+[10 Others],OTHERS,OTHERS/.text/[10 Others]
+foo::a,foo,foo/.text/a
+main,SECTIONS,SECTIONS/.text/main
+"
+        );
+    }
+
+    #[test]
+    fn test_names_are_interned() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::fmt,10,10
+.text,bar::fmt,20,20
+"#;
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        let foo = &tree.root.nodes["foo"].nodes[".text"].nodes["fmt"];
+        let bar = &tree.root.nodes["bar"].nodes[".text"].nodes["fmt"];
+        assert!(Rc::ptr_eq(&foo.name, &bar.name));
+        let (key, node) = tree.root.nodes["foo"].nodes[".text"]
+            .nodes
+            .get_key_value("fmt")
+            .expect("fmt node");
+        assert!(Rc::ptr_eq(key, &node.name));
+        assert_eq!(foo.filesize, 10);
+        assert_eq!(bar.filesize, 20);
+    }
+
+    #[test]
+    fn test_degenerate_symbols() {
+        let symbols = [
+            "",
+            "::",
+            ":::",
+            "a::",
+            "::a",
+            "<",
+            ">",
+            "<>",
+            "<<>>",
+            "<a as>",
+            "{closure#0}",
+            "..::x",
+            " ",
+        ];
+        let mut csv = String::from("sections,symbols,vmsize,filesize\n");
+        for symbol in symbols {
+            csv.push_str(&format!(".text,\"{symbol}\",1,1\n,\"{symbol}\",1,1\n"));
+        }
+        for options in [
+            ConvertOptions::default(),
+            ConvertOptions {
+                flatten_sections: true,
+                no_sections: true,
+                ..Default::default()
+            },
+        ] {
+            let tree = Tree::new(&csv, &options).expect("Failed to create tree");
+            assert!(tree.root.total_filesize <= 2 * symbols.len() as u64);
+        }
+        assert_eq!(path_crate(&[], 0), "");
+    }
+
+    #[test]
+    fn test_imports_order() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::d,10,10
+.text,foo::b,10,10
+.text,foo::e,20,20
+.text,foo::a,10,10
+.text,foo::c,10,10
+"#;
+        let options = ConvertOptions {
+            no_lock: true,
+            ..Default::default()
+        };
+        let meta = Tree::new(csv, &options)
+            .expect("Failed to create tree")
+            .to_metafile("BINARY", &options);
+        let imports: Vec<_> = meta.inputs["foo/.text"]
+            .imports
+            .iter()
+            .map(|i| i.path.as_str())
+            .collect();
+        // Largest first, equal sizes by name
+        assert_eq!(
+            imports,
+            [
+                "foo/.text/e",
+                "foo/.text/a",
+                "foo/.text/b",
+                "foo/.text/c",
+                "foo/.text/d"
+            ]
+        );
+
+        // The same JSON every time, whatever the hash map order
+        let json = |csv: &str| {
+            crate::to_json_string(
+                &Tree::new(csv, &options)
+                    .expect("Failed to create tree")
+                    .to_metafile("BINARY", &options),
+            )
+            .expect("Failed to serialize")
+        };
+        let first = json(csv);
+        for _ in 0..8 {
+            assert_eq!(json(csv), first);
         }
     }
+
+    #[test]
+    fn test_float_sizes() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,1086372.0,1086372.0
+.text,foo::b,1.2e3,1199.6
+"#;
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        let text = &tree.root.nodes["foo"].nodes[".text"];
+        assert_eq!(text.nodes["a"].vmsize, 1086372);
+        assert_eq!(text.nodes["a"].filesize, 1086372);
+        assert_eq!(text.nodes["b"].vmsize, 1200);
+        assert_eq!(text.nodes["b"].filesize, 1200);
+
+        // Negative sizes are still rejected
+        let csv = "sections,symbols,vmsize,filesize\n.text,foo::a,-1.0,1\n";
+        assert!(matches!(
+            Tree::new(csv, &ConvertOptions::default()),
+            Err(BloatyError::CsvParse(_))
+        ));
+    }
+
+    #[test]
+    fn test_percentage_columns() {
+        let csv = r#"
+sections,symbols,vmsize,vmsize%,filesize,filesize%
+.text,foo::a,10,66.7%,12,70.6%
+.text,foo::b,5,33.3%,5,29.4%
+"#;
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        assert_eq!(tree.root.total_vmsize, 15);
+        assert_eq!(tree.root.total_filesize, 17);
+        assert_eq!(
+            tree.root.nodes["foo"].nodes[".text"].nodes["a"].filesize,
+            12
+        );
+    }
+
+    #[test]
+    fn test_new_no_lock() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a::f,1,1
+.text,<bar::B as core::fmt::Debug>::fmt,2,2
+.text,std::io::stdio::_print,4,4
+.text,main,8,8
+"#;
+        let tree = Tree::new_no_lock(csv, false).expect("Failed to create tree");
+        let mut names: Vec<_> = tree.root.nodes.keys().map(|s| s.as_ref()).collect();
+        names.sort();
+        assert_eq!(names, vec!["SECTIONS", "bar", "foo", "std"]);
+        assert_eq!(
+            tree.root.nodes["foo"].nodes[".text"].nodes["a"].nodes["f"].filesize,
+            1
+        );
+        assert_eq!(
+            tree.root.nodes["bar"].nodes[".text"].nodes["B"].nodes["fmt"].filesize,
+            2
+        );
+
+        let tree = Tree::new_no_lock(csv, true).expect("Failed to create tree");
+        assert!(!tree.root.nodes.contains_key("SECTIONS"));
+        assert_eq!(tree.root.total_filesize, 7);
+    }
+
+    #[test]
+    fn test_crate_totals() {
+        let options = ConvertOptions {
+            no_lock: true,
+            ..Default::default()
+        };
+        let old = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,1,10
+.text,foo::b::c,1,20
+.text,bar::d,1,40
+.rodata,x.y,1,80
+"#;
+        let new = r#"
+sections,symbols,vmsize,filesize
+.text,foo::a,1,10
+.text,foo::b::c,1,20
+.text,bar::d,1,45
+.text,bar::e,1,5
+.rodata,x.y,1,80
+"#;
+        let old = Tree::new(old, &options).expect("Failed to create tree");
+        let new = Tree::new(new, &options).expect("Failed to create tree");
+        assert_eq!(
+            old.crate_totals(Weight::Bytes),
+            vec![
+                ("SECTIONS".to_string(), 80),
+                ("bar".to_string(), 40),
+                ("foo".to_string(), 30),
+            ]
+        );
+        assert_eq!(new.crate_totals(Weight::Count)[1], ("bar".to_string(), 2));
+
+        let report = diff_crates(
+            &old.crate_totals(Weight::Bytes),
+            &new.crate_totals(Weight::Bytes),
+        );
+        assert_eq!(report.crates.len(), 1);
+        assert_eq!(report.crates[0].path, "bar");
+        assert_eq!(
+            (report.crates[0].base_bytes, report.crates[0].current_bytes),
+            (40, 50)
+        );
+    }
+
+    #[test]
+    fn test_timings() {
+        let csv = "sections,symbols,vmsize,filesize\n.text,foo::a,1,1\n";
+        let tree = Tree::new(csv, &ConvertOptions::default()).expect("Failed to create tree");
+        let timings = tree.timings();
+        assert!(timings.build > Duration::ZERO);
+        assert_eq!(timings.convert, Duration::ZERO);
+        assert_eq!(timings.serialize, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_alias_merges_crates() {
+        let csv = r#"
+sections,symbols,vmsize,filesize
+.text,foo_vendored::a,10,10
+.text,foo::b,20,20
+"#;
+        let options = ConvertOptions {
+            aliases: [("foo-vendored".to_string(), "foo".to_string())].into(),
+            ..Default::default()
+        };
+        let tree = Tree::new(csv, &options).expect("Failed to create tree");
+        assert_eq!(tree.root.nodes.len(), 1);
+        let foo = &tree.root.nodes["foo"];
+        assert_eq!(foo.total_filesize, 30);
+        assert_eq!(foo.nodes[".text"].nodes.len(), 2);
+    }
 }
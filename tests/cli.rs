@@ -0,0 +1,217 @@
+use std::process::{Command, Output};
+
+/// Path of a file in `tests/fixtures`
+fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"))
+}
+
+/// Run the binary on `elf.csv`
+fn command(args: &[&str], envs: &[(&str, &str)]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_bloaty-metafile"))
+        .arg(fixture("elf.csv"))
+        .args(args)
+        .env_remove("BLOATY_METAFILE_NAME")
+        .env_remove("BLOATY_METAFILE_LOCK")
+        .env_remove("NO_COLOR")
+        .envs(envs.iter().copied())
+        .output()
+        .expect("Failed to run bloaty-metafile")
+}
+
+/// Run the binary on `elf.csv` and parse the metafile it prints
+fn run(args: &[&str], envs: &[(&str, &str)]) -> serde_json::Value {
+    let output = command(args, envs);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    serde_json::from_slice(&output.stdout).expect("Failed to parse metafile")
+}
+
+#[test]
+fn name_and_lock_from_env() {
+    let missing = fixture("missing.lock");
+    let lock = fixture("fixture.lock");
+
+    let meta = run(&["--no-lock"], &[]);
+    assert!(meta["outputs"].get("BINARY").is_some());
+    assert!(meta["inputs"].get("foo").is_some());
+
+    let meta = run(
+        &[],
+        &[
+            ("BLOATY_METAFILE_NAME", "app"),
+            ("BLOATY_METAFILE_LOCK", &lock),
+        ],
+    );
+    assert!(meta["outputs"].get("app").is_some());
+    assert!(meta["inputs"].get("app/foo").is_some());
+
+    // Flags take precedence over the environment
+    let meta = run(
+        &["--name", "flag", "--lock", &lock],
+        &[
+            ("BLOATY_METAFILE_NAME", "app"),
+            ("BLOATY_METAFILE_LOCK", &missing),
+        ],
+    );
+    assert!(meta["outputs"].get("flag").is_some());
+    assert!(meta["inputs"].get("app/foo").is_some());
+
+    let meta = run(&["--no-lock"], &[("BLOATY_METAFILE_LOCK", &lock)]);
+    assert!(meta["inputs"].get("foo").is_some());
+}
+
+#[test]
+fn explicit_lock_must_load() {
+    let missing = fixture("missing.lock");
+    for (args, envs) in [
+        (vec!["--lock", missing.as_str()], vec![]),
+        (vec![], vec![("BLOATY_METAFILE_LOCK", missing.as_str())]),
+    ] {
+        let output = command(&args, &envs);
+        assert!(!output.status.success());
+        let s = String::from_utf8(output.stderr).expect("utf8");
+        assert!(s.contains("Failed to load Cargo.lock"), "{s}");
+    }
+}
+
+#[test]
+fn no_color() {
+    let stderr = |args: &[&str], envs: &[(&str, &str)]| {
+        let output = command(args, envs);
+        String::from_utf8(output.stderr).expect("utf8")
+    };
+
+    let s = stderr(&["--no-lock", "--stats", "--color", "always"], &[]);
+    assert!(s.starts_with("\x1b[1mcrate"), "{s}");
+
+    let s = stderr(&["--no-lock", "--stats"], &[("NO_COLOR", "1")]);
+    assert!(s.starts_with("crate"), "{s}");
+    assert!(!s.contains('\x1b'), "{s}");
+
+    let output = command(&["--no-lock", "--root-at", "nope"], &[("NO_COLOR", "1")]);
+    assert!(!output.status.success());
+    let s = String::from_utf8(output.stderr).expect("utf8");
+    assert_eq!(s, "Error: No crate or node named `nope` in the tree\n");
+
+    let s = stderr(
+        &["--no-lock", "--root-at", "nope", "--color", "always"],
+        &[],
+    );
+    assert!(s.starts_with("\x1b[31mError: "), "{s}");
+}
+
+#[test]
+fn split_by_crate_file_names() {
+    let dir = std::env::temp_dir().join(format!("bloaty-metafile-split-{}", std::process::id()));
+    let dir_arg = dir.display().to_string();
+    let output = command(
+        &[
+            "--no-lock",
+            "--proc-macro",
+            "foo",
+            "--split-by-crate",
+            &dir_arg,
+        ],
+        &[],
+    );
+    assert!(output.status.success());
+
+    let index = std::fs::read_to_string(dir.join("index.json")).expect("Failed to read index");
+    let index: serde_json::Value = serde_json::from_str(&index).expect("Failed to parse index");
+    let entry = index
+        .as_array()
+        .expect("index is an array")
+        .iter()
+        .find(|entry| entry["name"] == "[proc-macro]")
+        .expect("proc-macro entry");
+    assert_eq!(entry["file"], "_proc-macro_.json");
+    assert!(dir.join("_proc-macro_.json").exists());
+    std::fs::remove_dir_all(&dir).expect("Failed to remove output");
+}
+
+#[test]
+fn relative_to_with_any_format() {
+    let base =
+        std::env::temp_dir().join(format!("bloaty-metafile-base-{}.json", std::process::id()));
+    let output = command(&["--no-lock"], &[]);
+    std::fs::write(&base, &output.stdout).expect("Failed to write baseline");
+    let base_arg = base.display().to_string();
+
+    for format in [
+        "metafile",
+        "nested",
+        "html",
+        "webtreemap",
+        "summary",
+        "ndjson",
+    ] {
+        let output = command(
+            &["--no-lock", "--format", format, "--relative-to", &base_arg],
+            &[],
+        );
+        assert!(output.status.success(), "{format}");
+        let stderr = String::from_utf8(output.stderr).expect("utf8");
+        assert!(stderr.contains("total: +0 bytes"), "{format}: {stderr}");
+    }
+    std::fs::remove_file(&base).expect("Failed to remove baseline");
+}
+
+#[test]
+fn timings_with_any_output() {
+    let old = fixture("elf.csv");
+    let mut outputs: Vec<Vec<&str>> = [
+        "metafile",
+        "nested",
+        "html",
+        "webtreemap",
+        "summary",
+        "ndjson",
+    ]
+    .iter()
+    .map(|format| vec!["--format", format])
+    .collect();
+    outputs.push(vec!["--compare", &old]);
+    outputs.push(vec!["--relative-to-crate", "foo"]);
+    for args in outputs {
+        let output = command(&[&["--no-lock", "--timings"], &args[..]].concat(), &[]);
+        assert!(output.status.success(), "{args:?}");
+        let stderr = String::from_utf8(output.stderr).expect("utf8");
+        assert!(stderr.contains("serialize "), "{args:?}: {stderr}");
+        assert!(stderr.contains("total "), "{args:?}: {stderr}");
+    }
+}
+
+#[test]
+fn pipe_with_any_output() {
+    let pipe =
+        std::env::temp_dir().join(format!("bloaty-metafile-pipe-{}.out", std::process::id()));
+    let pipe_arg = pipe.display().to_string();
+    let outputs: [&[&str]; 3] = [
+        &["--format", "nested"],
+        &["--format", "ndjson"],
+        &["--relative-to-crate", "foo"],
+    ];
+    for args in outputs {
+        let output = command(&[&["--no-lock", "--pipe", &pipe_arg], args].concat(), &[]);
+        assert!(output.status.success(), "{args:?}");
+        assert!(output.stdout.is_empty(), "{args:?}");
+        let written = std::fs::read_to_string(&pipe).expect("Failed to read pipe");
+        assert!(!written.is_empty(), "{args:?}");
+    }
+    std::fs::remove_file(&pipe).expect("Failed to remove pipe");
+
+    // --split-by-crate writes files, there is no output to redirect
+    let output = command(&["--split-by-crate", "out", "--pipe", &pipe_arg], &[]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn versions_without_lockfile() {
+    let output = command(&["--format", "versions", "--no-lock"], &[("NO_COLOR", "1")]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).expect("utf8");
+    assert!(stderr.contains("can't be used with --no-lock"), "{stderr}");
+}
@@ -0,0 +1,80 @@
+use bloaty_metafile::{ConvertOptions, from_csv_with_options};
+use serde_json::{Map, Value, json};
+
+/// Path of a file in `tests/fixtures`
+fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"))
+}
+
+/// Convert a CSV fixture and render the metafile as pretty printed JSON with a stable order
+/// Only the sizes and import paths are kept, so the snapshot doesn't depend on how
+/// `serde_metafile` serializes optional fields
+fn convert(csv: &str, lock: Option<&str>) -> String {
+    let csv = std::fs::read_to_string(fixture(csv)).expect("Failed to read fixture");
+    let options = ConvertOptions {
+        lock: lock.map(fixture),
+        // Without a lockfile every crate is a top-level node
+        no_lock: lock.is_none(),
+        ..Default::default()
+    };
+    let meta = from_csv_with_options(&csv, "BINARY", &options).expect("Failed to convert");
+
+    // serde_json::Map is sorted by key
+    let inputs: Map<String, Value> = meta
+        .inputs
+        .iter()
+        .map(|(path, input)| {
+            let mut imports: Vec<_> = input.imports.iter().map(|i| i.path.as_str()).collect();
+            imports.sort();
+            (
+                path.clone(),
+                json!({ "bytes": input.bytes, "imports": imports }),
+            )
+        })
+        .collect();
+    let outputs: Map<String, Value> = meta
+        .outputs
+        .iter()
+        .map(|(name, output)| {
+            let inputs: Map<String, Value> = output
+                .inputs
+                .iter()
+                .map(|(path, detail)| (path.clone(), json!(detail.bytes_in_output)))
+                .collect();
+            (
+                name.clone(),
+                json!({ "bytes": output.bytes, "inputs": inputs }),
+            )
+        })
+        .collect();
+    serde_json::to_string_pretty(&json!({ "inputs": inputs, "outputs": outputs }))
+        .expect("Failed to serialize")
+}
+
+#[test]
+fn snapshot_macho() {
+    insta::assert_snapshot!("macho", convert("macho.csv", None));
+}
+
+#[test]
+fn snapshot_elf() {
+    insta::assert_snapshot!("elf", convert("elf.csv", None));
+}
+
+#[test]
+fn snapshot_elf_with_lockfile() {
+    insta::assert_snapshot!(
+        "elf_with_lockfile",
+        convert("elf.csv", Some("fixture.lock"))
+    );
+}
+
+#[test]
+fn snapshot_angle_bracket() {
+    insta::assert_snapshot!("angle_bracket", convert("angle_bracket.csv", None));
+}
+
+#[test]
+fn snapshot_others() {
+    insta::assert_snapshot!("others", convert("others.csv", None));
+}